@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -9,6 +11,10 @@ pub struct KeywordArgument {
 
     pub repeatable: bool,
 
+    /// Whether the argument must be given for a command line to be valid.
+    #[serde(default)]
+    pub required: bool,
+
     pub style: KeywordArgumentStyle,
     pub value_type: ValueType,
 
@@ -16,14 +22,14 @@ pub struct KeywordArgument {
     pub incompatible_with: Vec<String>,
 }
 
-impl ToString for KeywordArgument {
-    fn to_string(&self) -> String {
+impl Display for KeywordArgument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let prefix = match self.style {
             KeywordArgumentStyle::Standard => "--",
             KeywordArgumentStyle::Old => "-",
         };
 
-        format!("{prefix}{}", self.name)
+        write!(f, "{prefix}{}", self.name)
     }
 }
 
@@ -42,6 +48,10 @@ pub struct PositionalArgument {
 
     pub value_type: ValueType,
 
+    /// Whether the argument must be given for a command line to be valid.
+    #[serde(default)]
+    pub required: bool,
+
     #[serde(default)]
     pub incompatible_with: Vec<String>,
 }
@@ -0,0 +1,214 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{CommandParseError, SchemaError},
+    Command, Completion, ParseState, ValueType,
+};
+
+/// A command which has been checked and indexed for completing quickly, suitable for caching.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompiledCommand {
+    command: Command,
+    index: CommandIndex,
+}
+
+impl Command {
+    /// Check that the command is consistent and index it for completion.
+    pub fn compile(self) -> Result<CompiledCommand, SchemaError> {
+        self.check()?;
+        let index = CommandIndex::new(&self);
+
+        Ok(CompiledCommand {
+            command: self,
+            index,
+        })
+    }
+
+    fn check(&self) -> Result<(), SchemaError> {
+        let mut names = HashSet::new();
+        for name in self
+            .keyword_arguments
+            .iter()
+            .map(|argument| &argument.name)
+            .chain(
+                self.positional_arguments
+                    .iter()
+                    .map(|argument| &argument.name),
+            )
+        {
+            if !names.insert(name) {
+                return Err(SchemaError::DuplicateArgument(name.clone()));
+            }
+        }
+
+        let incompatibilities = self
+            .keyword_arguments
+            .iter()
+            .map(|argument| (&argument.name, &argument.incompatible_with))
+            .chain(
+                self.positional_arguments
+                    .iter()
+                    .map(|argument| (&argument.name, &argument.incompatible_with)),
+            );
+        for (name, incompatible_with) in incompatibilities {
+            if let Some(unknown) = incompatible_with
+                .iter()
+                .find(|other| !names.contains(other))
+            {
+                return Err(SchemaError::UnknownIncompatibleArgument {
+                    argument: name.clone(),
+                    unknown: unknown.clone(),
+                });
+            }
+        }
+
+        let mut subcommand_names = HashSet::new();
+        for subcommand in &self.subcommands {
+            if !subcommand_names.insert(&subcommand.name) {
+                return Err(SchemaError::DuplicateSubcommand(subcommand.name.clone()));
+            }
+            subcommand.command.check()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl CompiledCommand {
+    pub fn command(&self) -> &Command {
+        &self.command
+    }
+
+    /// See [`Command::generate_candidates`].
+    pub fn generate_candidates(
+        &self,
+        command: &str,
+        cursor_position: usize,
+    ) -> Result<Vec<Completion>, CommandParseError> {
+        self.parse_state(command, cursor_position)?.candidates()
+    }
+
+    /// See [`Command::generate_candidates_from_words`].
+    pub fn generate_candidates_from_words(
+        &self,
+        words: &[String],
+    ) -> Result<Vec<Completion>, CommandParseError> {
+        self.parse_state_from_words(words)?.candidates()
+    }
+
+    /// See [`Command::parse_state`].
+    pub fn parse_state(
+        &self,
+        command: &str,
+        cursor_position: usize,
+    ) -> Result<ParseState<'_>, CommandParseError> {
+        let words = Command::split_line(command, cursor_position)?;
+        self.parse_state_from_words(&words)
+    }
+
+    /// See [`Command::parse_state_from_words`].
+    pub fn parse_state_from_words(
+        &self,
+        words: &[String],
+    ) -> Result<ParseState<'_>, CommandParseError> {
+        ParseState::new(&self.command, Some(&self.index), words)
+    }
+}
+
+/// Lookup tables for the names and values of a command and its subcommands, mirroring its
+/// structure.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CommandIndex {
+    pub keyword_arguments: PrefixIndex,
+    pub subcommands: PrefixIndex,
+
+    /// The values of each keyword argument which is an enumeration, by name.
+    pub keyword_values: HashMap<String, PrefixIndex>,
+
+    /// The values of each positional argument which is an enumeration, by position.
+    pub positional_values: Vec<Option<PrefixIndex>>,
+
+    pub subcommand_indexes: Vec<CommandIndex>,
+}
+
+impl CommandIndex {
+    fn new(command: &Command) -> Self {
+        CommandIndex {
+            keyword_arguments: PrefixIndex::new(
+                command
+                    .keyword_arguments
+                    .iter()
+                    .map(|argument| argument.name.as_str()),
+            ),
+            subcommands: PrefixIndex::new(
+                command
+                    .subcommands
+                    .iter()
+                    .map(|subcommand| subcommand.name.as_str()),
+            ),
+            keyword_values: command
+                .keyword_arguments
+                .iter()
+                .filter_map(|argument| {
+                    PrefixIndex::for_values(&argument.value_type)
+                        .map(|index| (argument.name.clone(), index))
+                })
+                .collect(),
+            positional_values: command
+                .positional_arguments
+                .iter()
+                .map(|argument| PrefixIndex::for_values(&argument.value_type))
+                .collect(),
+            subcommand_indexes: command
+                .subcommands
+                .iter()
+                .map(|subcommand| CommandIndex::new(&subcommand.command))
+                .collect(),
+        }
+    }
+}
+
+/// Strings sorted along with their original positions, so that those starting with a prefix can
+/// be found with a binary search.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct PrefixIndex {
+    entries: Vec<(String, usize)>,
+}
+
+impl PrefixIndex {
+    fn new<'a>(strings: impl Iterator<Item = &'a str>) -> Self {
+        let mut entries = strings
+            .enumerate()
+            .map(|(position, string)| (string.to_string(), position))
+            .collect::<Vec<_>>();
+        entries.sort();
+
+        PrefixIndex { entries }
+    }
+
+    fn for_values(value_type: &ValueType) -> Option<Self> {
+        match value_type {
+            ValueType::Enumeration(values) => {
+                Some(PrefixIndex::new(values.iter().map(String::as_str)))
+            }
+            _ => None,
+        }
+    }
+
+    /// The original positions of the strings starting with the prefix, in their original order.
+    pub fn matching(&self, prefix: &str) -> Vec<usize> {
+        let start = self
+            .entries
+            .partition_point(|(string, _)| string.as_str() < prefix);
+
+        let mut positions = self.entries[start..]
+            .iter()
+            .take_while(|(string, _)| string.starts_with(prefix))
+            .map(|(_, position)| *position)
+            .collect::<Vec<_>>();
+        positions.sort_unstable();
+        positions
+    }
+}
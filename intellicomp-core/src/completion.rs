@@ -0,0 +1,69 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// A single candidate produced when completing a command line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Completion {
+    /// The text that should be inserted at the cursor.
+    pub value: String,
+
+    /// A human-readable explanation of the candidate, for shells that are able to display one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Whether the shell should leave the cursor directly after the candidate rather than
+    /// appending a space, as the user is expected to carry on typing the same word.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub no_space: bool,
+
+    /// Whether the candidate is a path, so the shell can apply its usual handling of filenames.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub is_filename: bool,
+}
+
+impl Completion {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self {
+            value: value.into(),
+            description: None,
+            no_space: false,
+            is_filename: false,
+        }
+    }
+
+    pub fn with_description(value: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            value: value.into(),
+            description: Some(description.into()),
+            no_space: false,
+            is_filename: false,
+        }
+    }
+
+    /// Create a candidate for a path. Directories are given a trailing slash and no trailing
+    /// space, so that the user can carry on completing their contents.
+    pub fn for_path(path: &Path) -> Self {
+        Self::for_path_of_kind(path, path.is_dir())
+    }
+
+    /// Create a candidate for a path which may be relative to somewhere other than the working
+    /// directory, so whether it is a directory has already been found out.
+    pub(crate) fn for_path_of_kind(path: &Path, is_directory: bool) -> Self {
+        let mut value = path.to_string_lossy().to_string();
+        if is_directory && !value.ends_with('/') {
+            value.push('/');
+        }
+
+        Self {
+            value,
+            description: None,
+            no_space: is_directory,
+            is_filename: true,
+        }
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
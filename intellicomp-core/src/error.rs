@@ -19,4 +19,33 @@ pub enum CommandParseError {
 
     #[error("IOError: {0}")]
     IOError(#[from] glob::GlobError),
+
+    #[error("Invalid path pattern: {0}")]
+    InvalidPathPattern(#[from] glob::PatternError),
+}
+
+/// A problem with a schema which would stop it from being completed correctly.
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    #[error("More than one argument is named {0}")]
+    DuplicateArgument(String),
+
+    #[error("More than one subcommand is named {0}")]
+    DuplicateSubcommand(String),
+
+    #[error("The argument {argument} is incompatible with {unknown}, which doesn't exist")]
+    UnknownIncompatibleArgument { argument: String, unknown: String },
+}
+
+/// A problem applying an overlay to a command.
+#[derive(Debug, Error)]
+pub enum OverlayError {
+    #[error("The overlay refers to the argument {0}, which doesn't exist")]
+    UnknownArgument(String),
+
+    #[error("The overlay refers to the subcommand {0}, which doesn't exist")]
+    UnknownSubcommand(String),
+
+    #[error("The overlay changes the values of {0}, which isn't an enumeration")]
+    NotAnEnumeration(String),
 }
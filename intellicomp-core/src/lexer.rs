@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 use crate::error::CommandParseError;
 
 #[derive(Debug, PartialEq, Eq)]
@@ -46,9 +48,83 @@ pub fn parse_words(mut command: String) -> Result<Vec<String>, CommandParseError
     Ok(split_command)
 }
 
+/// A word of a command line along with the byte range it was read from, including any quotes.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Word {
+    pub value: String,
+    pub span: Range<usize>,
+}
+
+/// Split a complete command line into words following POSIX shell quoting rules, keeping track
+/// of where each word came from.
+///
+/// Unlike [`parse_words`] the line isn't assumed to end at the cursor, so an unterminated quote
+/// or trailing escape is an error, given as the span from where it started to the end of the
+/// line.
+pub fn split_words(line: &str) -> Result<Vec<Word>, Range<usize>> {
+    let mut words = vec![];
+    let mut current: Option<(String, usize)> = None;
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((index, char)) = chars.next() {
+        if char.is_whitespace() {
+            if let Some((value, start)) = current.take() {
+                words.push(Word {
+                    value,
+                    span: start..index,
+                });
+            }
+            continue;
+        }
+
+        let (value, _) = current.get_or_insert_with(|| (String::new(), index));
+        match char {
+            '\\' => match chars.next() {
+                // An escaped newline continues the line
+                Some((_, '\n')) => {}
+                Some((_, escaped)) => value.push(escaped),
+                None => return Err(index..line.len()),
+            },
+            '\'' => loop {
+                match chars.next() {
+                    Some((_, '\'')) => break,
+                    Some((_, quoted)) => value.push(quoted),
+                    None => return Err(index..line.len()),
+                }
+            },
+            '"' => loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.peek() {
+                        Some((_, '\n')) => {
+                            chars.next();
+                        }
+                        Some(&(_, escaped @ ('$' | '`' | '"' | '\\'))) => {
+                            chars.next();
+                            value.push(escaped);
+                        }
+                        _ => value.push('\\'),
+                    },
+                    Some((_, quoted)) => value.push(quoted),
+                    None => return Err(index..line.len()),
+                }
+            },
+            _ => value.push(char),
+        }
+    }
+
+    if let Some((value, start)) = current {
+        words.push(Word {
+            value,
+            span: start..line.len(),
+        });
+    }
+    Ok(words)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{get_quoting_state, parse_words, QuotingState};
+    use super::{get_quoting_state, parse_words, split_words, QuotingState, Word};
 
     #[test]
     fn test_get_quoting_state_no_quotes() {
@@ -147,4 +223,38 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn test_split_words_spans() {
+        let line = r#"command-name 'longer positional' "a \"b\""  c\ d"#;
+
+        assert_eq!(
+            split_words(line).expect("Failed to split command"),
+            vec![
+                Word {
+                    value: "command-name".to_string(),
+                    span: 0..12
+                },
+                Word {
+                    value: "longer positional".to_string(),
+                    span: 13..32
+                },
+                Word {
+                    value: "a \"b\"".to_string(),
+                    span: 33..42
+                },
+                Word {
+                    value: "c d".to_string(),
+                    span: 44..48
+                },
+            ]
+        )
+    }
+
+    #[test]
+    fn test_split_words_unterminated_quote() {
+        let line = "command-name --flag 'partial";
+
+        assert_eq!(split_words(line), Err(20..line.len()))
+    }
 }
@@ -1,15 +1,32 @@
+use std::{path::Path, time::Instant};
+
 use lexer::parse_words;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use self::error::CommandParseError;
-
 mod argument;
 pub use argument::{KeywordArgument, KeywordArgumentStyle, PositionalArgument, ValueType};
+
+mod compiled;
+pub use compiled::CompiledCommand;
+
+mod completion;
+pub use completion::Completion;
+
 mod error;
+pub use error::{CommandParseError, OverlayError, SchemaError};
 
 mod lexer;
 
+mod overlay;
+pub use overlay::{ArgumentOverlay, Overlay, SubcommandOverlay};
+
+mod state;
+pub use state::{Exclusion, ExclusionReason, ParseState, ParsedToken, Token};
+
+mod validate;
+pub use validate::{ParsedArgument, ParsedCommand, ValidationError, ValidationErrorKind};
+
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Command {
     /// A brief overview of the command
@@ -23,6 +40,11 @@ pub struct Command {
     /// flag.
     #[serde(default)]
     pub positional_arguments: Vec<PositionalArgument>,
+
+    /// Any subcommands of this command. A subcommand may only be given in place of the first
+    /// positional argument, after which only the arguments of the subcommand are valid.
+    #[serde(default)]
+    pub subcommands: Vec<Subcommand>,
     // / Whether the top-level arguments above are valid anywhere in the command or must appear
     // / before any subcommands.
     // /
@@ -31,144 +53,150 @@ pub struct Command {
     // pub arguments_valid_anywhere: bool,
 }
 
+/// A named command nested within another, such as `add` in `git remote add`.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Subcommand {
+    pub name: String,
+
+    #[serde(flatten)]
+    pub command: Command,
+}
+
 impl Command {
+    /// Generate the values that could be inserted at the cursor position.
+    ///
+    /// This is a convenience wrapper around [`Command::generate_candidates`] for shells that
+    /// are unable to display descriptions.
     pub fn generate_completions(
         &self,
         command: &str,
         cursor_position: usize,
     ) -> Result<Vec<String>, CommandParseError> {
-        if cursor_position > command.len() {
+        Ok(self
+            .generate_candidates(command, cursor_position)?
+            .into_iter()
+            .map(|completion| completion.value)
+            .collect())
+    }
+
+    /// Generate the candidates, along with their descriptions, that could be inserted at the
+    /// cursor position.
+    pub fn generate_candidates(
+        &self,
+        command: &str,
+        cursor_position: usize,
+    ) -> Result<Vec<Completion>, CommandParseError> {
+        self.parse_state(command, cursor_position)?.candidates()
+    }
+
+    /// Generate the candidates for a command line which the shell has already split into words.
+    /// The first word is the name of the command and the last is the word being completed.
+    pub fn generate_candidates_from_words(
+        &self,
+        words: &[String],
+    ) -> Result<Vec<Completion>, CommandParseError> {
+        self.parse_state_from_words(words)?.candidates()
+    }
+
+    /// Parse the command line up to the cursor position, which is a byte offset, to find out how
+    /// the word being completed will be treated.
+    pub fn parse_state(
+        &self,
+        command: &str,
+        cursor_position: usize,
+    ) -> Result<ParseState<'_>, CommandParseError> {
+        let words = Command::split_line(command, cursor_position)?;
+        self.parse_state_from_words(&words)
+    }
+
+    /// Parse a command line which the shell has already split into words. The first word is the
+    /// name of the command and the last is the word being completed.
+    pub fn parse_state_from_words(
+        &self,
+        words: &[String],
+    ) -> Result<ParseState<'_>, CommandParseError> {
+        ParseState::new(self, None, words)
+    }
+
+    /// Split the command line up to the cursor position into words.
+    fn split_line(command: &str, cursor_position: usize) -> Result<Vec<String>, CommandParseError> {
+        if !command.is_char_boundary(cursor_position) {
             return Err(CommandParseError::CursorOutOfRange(cursor_position));
         }
 
         let (command, _) = command.split_at(cursor_position);
+        log::debug!(line:? = command, cursor = cursor_position; "Completing line");
 
+        let started = Instant::now();
         let split_command = parse_words(command.to_string())?;
-        let mut tokens: Vec<Token> = vec![];
-
-        let mut positional_argument_index = 0;
-
-        let mut iterator = split_command.into_iter().skip(1);
-        while let Some(token) = iterator.next() {
-            if let Some(argument) = self
-                .keyword_arguments
-                .iter()
-                .find(|arg| arg.name == token.trim_start_matches("-"))
-            {
-                if argument.value_type == ValueType::Flag {
-                    continue;
-                }
-
-                // FIXME: Fails on positional arguments - need to treat them differently.
-                let value = iterator
-                    .next()
-                    .ok_or(CommandParseError::ArgumentMissingValue(token))?;
-
-                tokens.push(Token::PopulatedKeywordArgument { argument, value })
-            } else if positional_argument_index < self.positional_arguments.len() {
-                let argument = &self.positional_arguments[positional_argument_index];
-                positional_argument_index += 1;
-                tokens.push(Token::PopulatedPositionalArgument {
-                    argument,
-                    value: token,
-                })
-            } else {
-                tokens.push(Token::PartialKeywordArgument(
-                    token.trim_start_matches('-').to_string(),
-                ));
-                continue;
-            };
-        }
+        log::debug!(
+            phase = "lex",
+            elapsed_us = started.elapsed().as_micros() as u64,
+            words:? = split_command;
+            "Finished phase"
+        );
+
+        Ok(split_command)
+    }
 
-        dbg!(&tokens);
-
-        Ok(match tokens.last().unwrap() {
-            Token::PopulatedKeywordArgument { argument, value } => match &argument.value_type {
-                ValueType::Flag => unreachable!(),
-                ValueType::String => vec![],
-                ValueType::Path => self.get_path_completions(value)?,
-                ValueType::Enumeration(values) => values
-                    .iter()
-                    .cloned()
-                    .filter(|member| member.starts_with(value))
-                    .collect::<Vec<String>>(),
-            },
-            Token::PopulatedPositionalArgument { argument, value } => {
-                let mut results = self.get_valid_keyword_arguments(&tokens, value);
-
-                match &argument.value_type {
-                    ValueType::Flag => unreachable!(),
-                    ValueType::String => {}
-                    ValueType::Path => results.extend(self.get_path_completions(value)?),
-                    ValueType::Enumeration(values) => results.extend(
-                        values
-                            .iter()
-                            .cloned()
-                            .filter(|member| member.starts_with(value))
-                            .collect::<Vec<String>>(),
-                    ),
-                };
-
-                results
-            }
-            Token::PartialKeywordArgument(partial) => {
-                self.get_valid_keyword_arguments(&tokens, partial)
-            }
+    /// Find the keyword argument spelt by a word, either in full or by its shorthand.
+    fn find_keyword_argument(&self, word: &str) -> Option<&KeywordArgument> {
+        self.keyword_arguments.iter().find(|argument| {
+            word == argument.to_string()
+                || argument
+                    .shorthand
+                    .is_some_and(|shorthand| word.strip_prefix('-') == Some(&shorthand.to_string()))
         })
     }
 
-    fn get_valid_keyword_arguments(&self, tokens: &[Token], query: &str) -> Vec<String> {
-        self.keyword_arguments
-            .iter()
-            .filter_map(|outer_argument| {
-                if outer_argument.name.starts_with(query) {
-                    if !outer_argument.repeatable
-                        && tokens.iter().any(|token| {
-                            if let Token::PopulatedKeywordArgument { argument, value: _ } = token {
-                                argument.name == outer_argument.name
-                            } else {
-                                false
-                            }
-                        })
-                    {
-                        return None;
-                    }
-                    Some(outer_argument.to_string())
-                } else {
-                    None
-                }
-            })
-            .collect()
+    /// Find the keyword argument named by a word of the form `--name=value`, which must take a
+    /// value, along with the value.
+    fn find_keyword_argument_with_value<'w>(
+        &self,
+        word: &'w str,
+    ) -> Option<(&KeywordArgument, &'w str)> {
+        let (name, value) = word.split_once('=')?;
+        self.find_keyword_argument(name)
+            .filter(|argument| argument.value_type != ValueType::Flag)
+            .map(|argument| (argument, value))
     }
 
-    fn get_path_completions(&self, partial_path: &str) -> Result<Vec<String>, CommandParseError> {
+    fn get_path_completions(
+        &self,
+        partial_path: &str,
+        working_directory: &Path,
+    ) -> Result<Vec<Completion>, CommandParseError> {
         // This takes self so that future versions may have more advanced path filtering.
-        Ok(glob::glob(&format!("./{partial_path}*"))
-            .unwrap()
-            .map(|maybe_path| maybe_path.map(|path| path.to_string_lossy().to_string()))
-            .collect::<Result<Vec<String>, _>>()?)
+        let pattern = working_directory.join(partial_path);
+        let pattern = pattern
+            .to_str()
+            .ok_or(CommandParseError::InvalidCommandInput)?;
+
+        Ok(glob::glob(&format!("{}*", glob::Pattern::escape(pattern)))?
+            .map(|maybe_path| {
+                maybe_path.map(|path| {
+                    // Relative paths are given relative to the directory they were completed in
+                    let candidate = if Path::new(partial_path).is_absolute() {
+                        &path
+                    } else {
+                        path.strip_prefix(working_directory).unwrap_or(&path)
+                    };
+                    Completion::for_path_of_kind(candidate, path.is_dir())
+                })
+            })
+            .collect::<Result<Vec<Completion>, _>>()?)
     }
 }
 
-#[derive(Debug)]
-enum Token<'a> {
-    PopulatedKeywordArgument {
-        argument: &'a KeywordArgument,
-        value: String,
-    },
-    PopulatedPositionalArgument {
-        argument: &'a PositionalArgument,
-        value: String,
-    },
-    PartialKeywordArgument(String),
-}
-
 #[cfg(test)]
 mod tests {
+    use std::path::Path;
 
     use crate::{
-        argument::KeywordArgumentStyle, error::CommandParseError, Command, KeywordArgument,
-        PositionalArgument, ValueType,
+        argument::KeywordArgumentStyle,
+        error::{CommandParseError, OverlayError, SchemaError},
+        Command, Completion, ExclusionReason, KeywordArgument, ParsedArgument, PositionalArgument,
+        Subcommand, Token, ValidationError, ValidationErrorKind, ValueType,
     };
     use lazy_static::lazy_static;
 
@@ -182,6 +210,7 @@ mod tests {
                     incompatible_with: vec![],
                     style: KeywordArgumentStyle::Standard,
                     repeatable: false,
+                    required: false,
                     shorthand: Some('s'),
                     value_type: ValueType::Enumeration(vec![
                         "foo".to_string(),
@@ -195,6 +224,7 @@ mod tests {
                     incompatible_with: vec![],
                     style: KeywordArgumentStyle::Standard,
                     repeatable: false,
+                    required: false,
                     shorthand: Some('s'),
                     value_type: ValueType::Path,
                 }
@@ -207,8 +237,57 @@ mod tests {
                     "2".to_string(),
                     "3".to_string()
                 ]),
+                required: false,
                 incompatible_with: vec![]
             }],
+            subcommands: vec![],
+        };
+        static ref MOCK_COMMAND_WITH_SUBCOMMANDS: Command = Command {
+            description: "This is a mock command with subcommands used for testing".to_string(),
+            keyword_arguments: vec![
+                KeywordArgument {
+                    name: "verbose".to_string(),
+                    description: "Print more output".to_string(),
+                    incompatible_with: vec![],
+                    style: KeywordArgumentStyle::Standard,
+                    repeatable: false,
+                    required: false,
+                    shorthand: Some('v'),
+                    value_type: ValueType::Flag,
+                },
+                KeywordArgument {
+                    name: "quiet".to_string(),
+                    description: "Print less output".to_string(),
+                    incompatible_with: vec!["verbose".to_string()],
+                    style: KeywordArgumentStyle::Standard,
+                    repeatable: false,
+                    required: false,
+                    shorthand: Some('q'),
+                    value_type: ValueType::Flag,
+                }
+            ],
+            positional_arguments: vec![],
+            subcommands: vec![Subcommand {
+                name: "remote".to_string(),
+                command: Command {
+                    description: "Manage remotes".to_string(),
+                    keyword_arguments: vec![KeywordArgument {
+                        name: "enum".to_string(),
+                        description: "Some argument".to_string(),
+                        incompatible_with: vec![],
+                        style: KeywordArgumentStyle::Standard,
+                        repeatable: false,
+                        required: false,
+                        shorthand: None,
+                        value_type: ValueType::Enumeration(vec![
+                            "foo".to_string(),
+                            "bar".to_string(),
+                        ]),
+                    }],
+                    positional_arguments: vec![],
+                    subcommands: vec![],
+                },
+            }],
         };
     }
 
@@ -233,11 +312,18 @@ mod tests {
             .unwrap()
             .map(|path| {
                 path.map(|path| {
-                    path.path()
+                    let name = path
+                        .path()
                         .to_string_lossy()
                         .strip_prefix("./")
                         .unwrap()
-                        .to_string()
+                        .to_string();
+
+                    if path.path().is_dir() {
+                        format!("{name}/")
+                    } else {
+                        name
+                    }
                 })
             })
             .collect::<Result<Vec<String>, _>>()
@@ -249,6 +335,22 @@ mod tests {
         assert_eq!(completions, expected)
     }
 
+    #[test]
+    fn test_directory_completions_have_no_trailing_space() {
+        let command = "command-name --file s";
+        let candidates = MOCK_COMMAND
+            .generate_candidates(command, command.len())
+            .unwrap();
+
+        let directory = candidates
+            .iter()
+            .find(|candidate| candidate.value == "src/")
+            .expect("The src directory was not completed");
+
+        assert!(directory.no_space);
+        assert!(directory.is_filename);
+    }
+
     #[test]
     fn test_generate_keyword_and_positional_completions() {
         let command = "command-name ";
@@ -258,7 +360,7 @@ mod tests {
 
         assert_eq!(
             completions.as_ref(),
-            vec!["--enum", "--file", "1", "2", "3"]
+            vec!["--enum=", "--file=", "1", "2", "3"]
         )
     }
 
@@ -269,7 +371,7 @@ mod tests {
             .generate_completions(command, command.len())
             .unwrap();
 
-        assert_eq!(completions.as_ref(), vec!["--enum", "--file"])
+        assert_eq!(completions.as_ref(), vec!["--enum=", "--file="])
     }
 
     #[test]
@@ -281,6 +383,112 @@ mod tests {
 
         assert_eq!(completions.as_ref(), vec!["bar", "baz"])
     }
+
+    #[test]
+    fn test_generate_candidates_with_descriptions() {
+        let command = "command-name 1 --f";
+        let candidates = MOCK_COMMAND
+            .generate_candidates(command, command.len())
+            .unwrap();
+
+        assert_eq!(
+            candidates.as_ref(),
+            vec![Completion {
+                no_space: true,
+                ..Completion::with_description("--file=", "Some argument")
+            }]
+        )
+    }
+
+    #[test]
+    fn test_generate_completions_after_equals() {
+        let command = "command-name --enum=ba";
+        let candidates = MOCK_COMMAND
+            .generate_candidates(command, command.len())
+            .unwrap();
+        assert_eq!(
+            candidates,
+            vec![Completion::new("--enum=bar"), Completion::new("--enum=baz")]
+        );
+
+        let command = "command-name --file=sr";
+        let candidates = MOCK_COMMAND
+            .generate_candidates(command, command.len())
+            .unwrap();
+        assert_eq!(candidates.len(), 1, "{candidates:?}");
+        assert_eq!(candidates[0].value, "--file=src/");
+        assert!(candidates[0].no_space);
+        assert!(!candidates[0].is_filename);
+
+        // The argument counts as given, so it isn't offered again
+        let command = "command-name --enum=foo --";
+        let completions = MOCK_COMMAND
+            .generate_completions(command, command.len())
+            .unwrap();
+        assert_eq!(completions.as_ref(), vec!["--file="])
+    }
+
+    #[test]
+    fn test_generate_subcommand_completions() {
+        let command = "command-name ";
+        let completions = MOCK_COMMAND_WITH_SUBCOMMANDS
+            .generate_completions(command, command.len())
+            .unwrap();
+
+        assert_eq!(completions.as_ref(), vec!["--verbose", "--quiet", "remote"])
+    }
+
+    #[test]
+    fn test_generate_completions_within_subcommand() {
+        let command = "command-name --verbose remote --enum b";
+        let completions = MOCK_COMMAND_WITH_SUBCOMMANDS
+            .generate_completions(command, command.len())
+            .unwrap();
+
+        assert_eq!(completions.as_ref(), vec!["bar"])
+    }
+
+    #[test]
+    fn test_exclude_incompatible_and_used_arguments() {
+        let command = "command-name --verbose --";
+        let completions = MOCK_COMMAND_WITH_SUBCOMMANDS
+            .generate_completions(command, command.len())
+            .unwrap();
+
+        assert!(completions.is_empty(), "{completions:?}")
+    }
+
+    #[test]
+    fn test_flag_does_not_consume_the_next_word() {
+        let command = "command-name --verbose re";
+        let completions = MOCK_COMMAND_WITH_SUBCOMMANDS
+            .generate_completions(command, command.len())
+            .unwrap();
+
+        assert_eq!(completions.as_ref(), vec!["remote"])
+    }
+
+    #[test]
+    fn test_subcommand_being_completed_is_not_entered() {
+        let command = "command-name remote";
+        let completions = MOCK_COMMAND_WITH_SUBCOMMANDS
+            .generate_completions(command, command.len())
+            .unwrap();
+
+        assert_eq!(completions.as_ref(), vec!["remote"])
+    }
+
+    #[test]
+    fn test_incompatibility_applies_both_ways() {
+        // Only quiet declares the incompatibility, but it rules out verbose too
+        let command = "command-name --quiet --";
+        let completions = MOCK_COMMAND_WITH_SUBCOMMANDS
+            .generate_completions(command, command.len())
+            .unwrap();
+
+        assert!(completions.is_empty(), "{completions:?}")
+    }
+
     #[test]
     fn test_cursor_out_of_range() {
         let command = "command-name --enum ";
@@ -295,4 +503,476 @@ mod tests {
             panic!("Wrong error variant: {error:?}")
         }
     }
+
+    #[test]
+    fn test_cursor_within_character() {
+        let command = "command-name --enum é";
+        let index = command.len() - 1;
+        let error = MOCK_COMMAND
+            .generate_completions(command, index)
+            .unwrap_err();
+
+        assert!(
+            matches!(error, CommandParseError::CursorOutOfRange(position) if position == index),
+            "Wrong error variant: {error:?}"
+        )
+    }
+
+    #[test]
+    fn test_empty_command_is_an_error() {
+        let error = MOCK_COMMAND.generate_completions("", 0).unwrap_err();
+
+        assert!(
+            matches!(error, CommandParseError::InvalidCommandInput),
+            "Wrong error variant: {error:?}"
+        )
+    }
+
+    #[test]
+    fn test_path_completions_from_another_directory() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-core-paths-{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("schemas")).unwrap();
+        std::fs::write(directory.join("schema.yaml"), "").unwrap();
+
+        let command = "command-name --file sch";
+        let mut completions = MOCK_COMMAND
+            .parse_state(command, command.len())
+            .unwrap()
+            .candidates_in(&directory)
+            .unwrap();
+        completions.sort_by(|a, b| a.value.cmp(&b.value));
+
+        assert_eq!(
+            completions,
+            vec![
+                Completion::for_path_of_kind(Path::new("schema.yaml"), false),
+                Completion::for_path_of_kind(Path::new("schemas"), true),
+            ]
+        );
+        assert_eq!(completions[1].value, "schemas/");
+
+        // Absolute paths are left as they are
+        let command = format!("command-name --file {}/schemas", directory.display());
+        let completions = MOCK_COMMAND
+            .parse_state(&command, command.len())
+            .unwrap()
+            .candidates_in(Path::new("/nonexistent"))
+            .unwrap();
+        assert_eq!(
+            completions
+                .into_iter()
+                .map(|completion| completion.value)
+                .collect::<Vec<_>>(),
+            vec![format!("{}/schemas/", directory.display())]
+        );
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_no_words_is_an_error() {
+        let error = MOCK_COMMAND
+            .generate_candidates_from_words(&[])
+            .unwrap_err();
+        assert!(
+            matches!(error, CommandParseError::InvalidCommandInput),
+            "Wrong error variant: {error:?}"
+        );
+
+        let compiled =
+            serde_json::from_value::<Command>(serde_json::to_value(&*MOCK_COMMAND).unwrap())
+                .unwrap()
+                .compile()
+                .unwrap();
+        assert!(compiled.generate_candidates_from_words(&[]).is_err());
+    }
+
+    #[test]
+    fn test_no_completions_within_command_name() {
+        let command = "command-na";
+        let completions = MOCK_COMMAND
+            .generate_completions(command, command.len())
+            .unwrap();
+
+        assert!(completions.is_empty(), "{completions:?}")
+    }
+
+    #[test]
+    fn test_path_with_glob_characters() {
+        let command = "command-name --file [";
+        let completions = MOCK_COMMAND
+            .generate_completions(command, command.len())
+            .unwrap();
+
+        assert!(completions.is_empty(), "{completions:?}")
+    }
+
+    #[test]
+    fn test_keyword_completions_before_positional() {
+        let command = "command-name --f";
+        let completions = MOCK_COMMAND
+            .generate_completions(command, command.len())
+            .unwrap();
+
+        assert_eq!(completions.as_ref(), vec!["--file="])
+    }
+
+    #[test]
+    fn test_parse_state_tokens() {
+        let command = "command-name --verbose remote --enum b";
+        let state = MOCK_COMMAND_WITH_SUBCOMMANDS
+            .parse_state(command, command.len())
+            .unwrap();
+
+        let tokens = state.tokens();
+        assert_eq!(tokens.len(), 3, "{tokens:?}");
+        assert!(matches!(tokens[0].token, Token::Flag(argument) if argument.name == "verbose"));
+        assert!(
+            matches!(tokens[1].token, Token::Subcommand(subcommand) if subcommand.name == "remote")
+        );
+        assert!(matches!(
+            &tokens[2].token,
+            Token::PopulatedKeywordArgument { argument, value } if argument.name == "enum" && value == "b"
+        ));
+        assert_eq!(tokens[2].words, 3..5);
+        assert_eq!(state.command().description, "Manage remotes");
+    }
+
+    #[test]
+    fn test_excluded_arguments() {
+        let command = "command-name --verbose --";
+        let state = MOCK_COMMAND_WITH_SUBCOMMANDS
+            .parse_state(command, command.len())
+            .unwrap();
+
+        let exclusions = state
+            .excluded_arguments()
+            .into_iter()
+            .map(|exclusion| (exclusion.argument.name.as_str(), exclusion.reason))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            exclusions,
+            vec![
+                ("verbose", ExclusionReason::AlreadyUsed),
+                (
+                    "quiet",
+                    ExclusionReason::IncompatibleWith("verbose".to_string())
+                )
+            ]
+        )
+    }
+
+    #[test]
+    fn test_parse_valid_command() {
+        let parsed = MOCK_COMMAND_WITH_SUBCOMMANDS
+            .parse("command-name --verbose remote --enum foo")
+            .unwrap();
+
+        assert_eq!(parsed.arguments.len(), 1);
+        let subcommand = parsed.subcommand.expect("The subcommand was not parsed");
+        assert_eq!(subcommand.name, "remote");
+        assert_eq!(subcommand.span, 23..29);
+        assert!(matches!(
+            &subcommand.arguments[..],
+            [ParsedArgument::Keyword { argument, value, span }]
+                if argument.name == "enum" && value == "foo" && *span == (30..40)
+        ));
+    }
+
+    #[test]
+    fn test_validate_reports_every_error() {
+        let errors = MOCK_COMMAND.validate("command-name --bogus 4 --enum qux 1 2 --file");
+
+        assert_eq!(
+            errors,
+            vec![
+                ValidationError {
+                    kind: ValidationErrorKind::UnknownOption("--bogus".to_string()),
+                    span: 13..20
+                },
+                ValidationError {
+                    kind: ValidationErrorKind::InvalidEnumMember {
+                        argument: "positional".to_string(),
+                        value: "4".to_string(),
+                        expected: vec!["1".to_string(), "2".to_string(), "3".to_string()]
+                    },
+                    span: 21..22
+                },
+                ValidationError {
+                    kind: ValidationErrorKind::InvalidEnumMember {
+                        argument: "--enum".to_string(),
+                        value: "qux".to_string(),
+                        expected: vec!["foo".to_string(), "bar".to_string(), "baz".to_string()]
+                    },
+                    span: 30..33
+                },
+                ValidationError {
+                    kind: ValidationErrorKind::TooManyPositionals("1".to_string()),
+                    span: 34..35
+                },
+                ValidationError {
+                    kind: ValidationErrorKind::TooManyPositionals("2".to_string()),
+                    span: 36..37
+                },
+                ValidationError {
+                    kind: ValidationErrorKind::MissingValue("--file".to_string()),
+                    span: 38..44
+                },
+            ]
+        )
+    }
+
+    #[test]
+    fn test_validate_incompatible_and_repeated_arguments() {
+        let errors =
+            MOCK_COMMAND_WITH_SUBCOMMANDS.validate("command-name --verbose --quiet --verbose");
+
+        assert_eq!(
+            errors,
+            vec![
+                ValidationError {
+                    kind: ValidationErrorKind::IncompatibleArguments {
+                        argument: "--quiet".to_string(),
+                        other: "--verbose".to_string()
+                    },
+                    span: 23..30
+                },
+                ValidationError {
+                    kind: ValidationErrorKind::RepeatedArgument("--verbose".to_string()),
+                    span: 31..40
+                },
+            ]
+        )
+    }
+
+    #[test]
+    fn test_validate_missing_required_argument() {
+        let command: Command = serde_json::from_str(
+            r#"{
+                "description": "A command with a required argument",
+                "positional_arguments": [{
+                    "name": "target",
+                    "description": "Where to go",
+                    "value_type": { "type": "Path" },
+                    "required": true
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            command.validate("cmd"),
+            vec![ValidationError {
+                kind: ValidationErrorKind::MissingRequiredArgument("target".to_string()),
+                span: 0..3
+            }]
+        );
+        assert!(command.validate("cmd somewhere").is_empty());
+    }
+
+    #[test]
+    fn test_validate_options_after_double_dash_are_positional() {
+        assert!(MOCK_COMMAND.validate("command-name -- 2").is_empty());
+        assert_eq!(
+            MOCK_COMMAND.validate("command-name --enum foo -- --file"),
+            vec![ValidationError {
+                kind: ValidationErrorKind::InvalidEnumMember {
+                    argument: "positional".to_string(),
+                    value: "--file".to_string(),
+                    expected: vec!["1".to_string(), "2".to_string(), "3".to_string()]
+                },
+                span: 27..33
+            }]
+        );
+        assert_eq!(
+            MOCK_COMMAND_WITH_SUBCOMMANDS.validate("command-name -- remote"),
+            vec![ValidationError {
+                kind: ValidationErrorKind::TooManyPositionals("remote".to_string()),
+                span: 16..22
+            }]
+        );
+    }
+
+    #[test]
+    fn test_validate_keyword_argument_with_equals() {
+        let parsed = MOCK_COMMAND.parse("command-name --enum=bar").unwrap();
+        assert!(matches!(
+            &parsed.arguments[..],
+            [ParsedArgument::Keyword { argument, value, span }]
+                if argument.name == "enum" && value == "bar" && *span == (13..23)
+        ));
+
+        assert_eq!(
+            MOCK_COMMAND.validate("command-name --enum=qux"),
+            vec![ValidationError {
+                kind: ValidationErrorKind::InvalidEnumMember {
+                    argument: "--enum".to_string(),
+                    value: "qux".to_string(),
+                    expected: vec!["foo".to_string(), "bar".to_string(), "baz".to_string()]
+                },
+                span: 20..23
+            }]
+        );
+        assert_eq!(
+            MOCK_COMMAND_WITH_SUBCOMMANDS.validate("command-name --verbose=yes"),
+            vec![ValidationError {
+                kind: ValidationErrorKind::UnknownOption("--verbose=yes".to_string()),
+                span: 13..26
+            }]
+        );
+    }
+
+    #[test]
+    fn test_validate_unterminated_quote() {
+        assert_eq!(
+            MOCK_COMMAND.validate("command-name 'oops"),
+            vec![ValidationError {
+                kind: ValidationErrorKind::UnterminatedWord,
+                span: 13..18
+            }]
+        )
+    }
+
+    #[test]
+    fn test_compiled_candidates_match_uncompiled() {
+        for command in [&*MOCK_COMMAND, &*MOCK_COMMAND_WITH_SUBCOMMANDS] {
+            let compiled =
+                serde_json::from_value::<Command>(serde_json::to_value(command).unwrap())
+                    .unwrap()
+                    .compile()
+                    .unwrap();
+
+            for line in [
+                "command-name ",
+                "command-name --",
+                "command-name --enum ba",
+                "command-name 2",
+                "command-name r",
+                "command-name --verbose remote --enum b",
+                "command-name remote -",
+            ] {
+                assert_eq!(
+                    compiled.generate_candidates(line, line.len()).unwrap(),
+                    command.generate_candidates(line, line.len()).unwrap(),
+                    "{line}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_compile_rejects_inconsistent_commands() {
+        let command: Command = serde_json::from_str(
+            r#"{
+                "description": "A command with a mistake",
+                "keyword_arguments": [{
+                    "name": "quiet",
+                    "description": "Print less output",
+                    "style": "Standard",
+                    "repeatable": false,
+                    "incompatible_with": ["verbose"],
+                    "value_type": { "type": "Flag" }
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            command.compile().unwrap_err(),
+            SchemaError::UnknownIncompatibleArgument { argument, unknown }
+                if argument == "quiet" && unknown == "verbose"
+        ));
+    }
+
+    #[test]
+    fn test_apply_overlay() {
+        let mut command = serde_json::from_value::<Command>(
+            serde_json::to_value(&*MOCK_COMMAND_WITH_SUBCOMMANDS).unwrap(),
+        )
+        .unwrap();
+
+        let overlay = serde_json::from_str(
+            r#"{
+                "description": "Patched",
+                "remove_arguments": ["verbose"],
+                "add_keyword_arguments": [{
+                    "name": "internal",
+                    "description": "An internal flag",
+                    "shorthand": null,
+                    "repeatable": false,
+                    "style": "Standard",
+                    "value_type": { "type": "Flag" }
+                }],
+                "modify_subcommands": [{
+                    "name": "remote",
+                    "modify_arguments": [{
+                        "name": "enum",
+                        "description": "Patched argument",
+                        "add_values": ["qux"],
+                        "remove_values": ["foo"]
+                    }]
+                }]
+            }"#,
+        )
+        .unwrap();
+        command.apply_overlay(overlay).unwrap();
+
+        assert_eq!(command.description, "Patched");
+        // The incompatibility with the removed argument is removed along with it
+        assert!(command.keyword_arguments[0].incompatible_with.is_empty());
+
+        let command = command.compile().unwrap();
+        let line = "command-name ";
+        assert_eq!(
+            command.generate_candidates(line, line.len()).unwrap(),
+            vec![
+                Completion::with_description("--quiet", "Print less output"),
+                Completion::with_description("--internal", "An internal flag"),
+                Completion::with_description("remote", "Manage remotes"),
+            ]
+        );
+
+        let line = "command-name remote --enum ";
+        assert_eq!(
+            command.generate_candidates(line, line.len()).unwrap(),
+            vec![Completion::new("bar"), Completion::new("qux")]
+        );
+        assert_eq!(
+            command.command().subcommands[0].command.keyword_arguments[0].description,
+            "Patched argument"
+        );
+    }
+
+    #[test]
+    fn test_overlay_of_unknown_argument_is_skipped() {
+        let mut command =
+            serde_json::from_value::<Command>(serde_json::to_value(&*MOCK_COMMAND).unwrap())
+                .unwrap();
+
+        let overlay = serde_json::from_str(
+            r#"{ "modify_arguments": [{ "name": "file", "values": ["a"] }] }"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            command.apply_overlay(overlay),
+            Err(OverlayError::NotAnEnumeration(name)) if name == "file"
+        ));
+
+        // Arguments and subcommands the schema has since dropped don't stop the rest of the
+        // overlay from applying
+        let overlay = serde_json::from_str(
+            r#"{
+                "description": "Patched",
+                "remove_arguments": ["missing"],
+                "modify_arguments": [{ "name": "missing", "description": "Gone" }],
+                "remove_subcommands": ["missing"],
+                "modify_subcommands": [{ "name": "missing", "description": "Gone" }]
+            }"#,
+        )
+        .unwrap();
+        command.apply_overlay(overlay).unwrap();
+        assert_eq!(command.description, "Patched");
+    }
 }
@@ -0,0 +1,224 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::OverlayError, Command, KeywordArgument, PositionalArgument, Subcommand, ValueType,
+};
+
+/// Changes to a command, so that a schema maintained elsewhere can be adjusted without copying
+/// it.
+///
+/// Arguments and subcommands are removed first, then modified, then added.
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct Overlay {
+    /// A replacement for the description of the command.
+    pub description: Option<String>,
+
+    /// The names of keyword or positional arguments to remove. They're also removed from the
+    /// arguments which are incompatible with them.
+    pub remove_arguments: Vec<String>,
+
+    /// Changes to existing keyword or positional arguments.
+    pub modify_arguments: Vec<ArgumentOverlay>,
+
+    /// Keyword arguments to add, each replacing any argument of the same name.
+    pub add_keyword_arguments: Vec<KeywordArgument>,
+
+    /// Positional arguments to add after the existing ones, each replacing any argument of the
+    /// same name instead.
+    pub add_positional_arguments: Vec<PositionalArgument>,
+
+    /// The names of subcommands to remove.
+    pub remove_subcommands: Vec<String>,
+
+    /// Overlays for existing subcommands.
+    pub modify_subcommands: Vec<SubcommandOverlay>,
+
+    /// Subcommands to add, each replacing any subcommand of the same name.
+    pub add_subcommands: Vec<Subcommand>,
+}
+
+/// Changes to an existing keyword or positional argument.
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArgumentOverlay {
+    pub name: String,
+
+    /// A replacement for the description of the argument.
+    pub description: Option<String>,
+
+    /// A replacement for the members of an enumeration.
+    pub values: Option<Vec<String>>,
+
+    /// Members to add to an enumeration, after any replacement.
+    pub add_values: Vec<String>,
+
+    /// Members to remove from an enumeration.
+    pub remove_values: Vec<String>,
+}
+
+/// An overlay for the subcommand with the given name.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SubcommandOverlay {
+    pub name: String,
+
+    #[serde(flatten)]
+    pub overlay: Overlay,
+}
+
+impl Command {
+    /// Apply an overlay to the command. Every shell sees the same command once overlays have been
+    /// applied, since completion and validation only ever use the result.
+    ///
+    /// Changes to arguments and subcommands which don't exist are skipped with a warning, since
+    /// the schema may have dropped them after the overlay was written.
+    pub fn apply_overlay(&mut self, overlay: Overlay) -> Result<(), OverlayError> {
+        if let Some(description) = overlay.description {
+            self.description = description;
+        }
+
+        for name in &overlay.remove_arguments {
+            self.remove_argument(name);
+        }
+
+        for argument_overlay in overlay.modify_arguments {
+            self.modify_argument(argument_overlay)?;
+        }
+
+        for argument in overlay.add_keyword_arguments {
+            match self
+                .keyword_arguments
+                .iter_mut()
+                .find(|existing| existing.name == argument.name)
+            {
+                Some(existing) => *existing = argument,
+                None => self.keyword_arguments.push(argument),
+            }
+        }
+
+        for argument in overlay.add_positional_arguments {
+            match self
+                .positional_arguments
+                .iter_mut()
+                .find(|existing| existing.name == argument.name)
+            {
+                Some(existing) => *existing = argument,
+                None => self.positional_arguments.push(argument),
+            }
+        }
+
+        for name in &overlay.remove_subcommands {
+            if let Some(position) = self.find_subcommand(name) {
+                self.subcommands.remove(position);
+            }
+        }
+
+        for subcommand_overlay in overlay.modify_subcommands {
+            if let Some(position) = self.find_subcommand(&subcommand_overlay.name) {
+                self.subcommands[position]
+                    .command
+                    .apply_overlay(subcommand_overlay.overlay)?;
+            }
+        }
+
+        for subcommand in overlay.add_subcommands {
+            match self
+                .subcommands
+                .iter_mut()
+                .find(|existing| existing.name == subcommand.name)
+            {
+                Some(existing) => *existing = subcommand,
+                None => self.subcommands.push(subcommand),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn remove_argument(&mut self, name: &str) {
+        let keyword_arguments = self.keyword_arguments.len();
+        let positional_arguments = self.positional_arguments.len();
+        self.keyword_arguments
+            .retain(|argument| argument.name != name);
+        self.positional_arguments
+            .retain(|argument| argument.name != name);
+
+        if self.keyword_arguments.len() == keyword_arguments
+            && self.positional_arguments.len() == positional_arguments
+        {
+            log::warn!("{}", OverlayError::UnknownArgument(name.to_string()));
+            return;
+        }
+
+        let incompatibilities = self
+            .keyword_arguments
+            .iter_mut()
+            .map(|argument| &mut argument.incompatible_with)
+            .chain(
+                self.positional_arguments
+                    .iter_mut()
+                    .map(|argument| &mut argument.incompatible_with),
+            );
+        for incompatible_with in incompatibilities {
+            incompatible_with.retain(|other| other != name);
+        }
+    }
+
+    fn modify_argument(&mut self, overlay: ArgumentOverlay) -> Result<(), OverlayError> {
+        let (description, value_type) = if let Some(argument) = self
+            .keyword_arguments
+            .iter_mut()
+            .find(|argument| argument.name == overlay.name)
+        {
+            (&mut argument.description, &mut argument.value_type)
+        } else if let Some(argument) = self
+            .positional_arguments
+            .iter_mut()
+            .find(|argument| argument.name == overlay.name)
+        {
+            (&mut argument.description, &mut argument.value_type)
+        } else {
+            log::warn!("{}", OverlayError::UnknownArgument(overlay.name));
+            return Ok(());
+        };
+
+        if let Some(new_description) = overlay.description {
+            *description = new_description;
+        }
+
+        let changes_values = overlay.values.is_some()
+            || !overlay.add_values.is_empty()
+            || !overlay.remove_values.is_empty();
+        if !changes_values {
+            return Ok(());
+        }
+
+        let ValueType::Enumeration(members) = value_type else {
+            return Err(OverlayError::NotAnEnumeration(overlay.name));
+        };
+
+        if let Some(values) = overlay.values {
+            *members = values;
+        }
+        members.retain(|member| !overlay.remove_values.contains(member));
+        for value in overlay.add_values {
+            if !members.contains(&value) {
+                members.push(value);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find_subcommand(&self, name: &str) -> Option<usize> {
+        let position = self
+            .subcommands
+            .iter()
+            .position(|subcommand| subcommand.name == name);
+        if position.is_none() {
+            log::warn!("{}", OverlayError::UnknownSubcommand(name.to_string()));
+        }
+        position
+    }
+}
@@ -0,0 +1,464 @@
+use std::{fmt::Display, ops::Range, path::Path, time::Instant};
+
+use crate::{
+    compiled::{CommandIndex, PrefixIndex},
+    error::CommandParseError,
+    Command, Completion, KeywordArgument, KeywordArgumentStyle, PositionalArgument, Subcommand,
+    ValueType,
+};
+
+/// The meaning given to one or more words of a command line.
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum Token<'a> {
+    /// A keyword argument which takes no value.
+    Flag(&'a KeywordArgument),
+
+    /// A keyword argument along with the word following it, which is its value.
+    PopulatedKeywordArgument {
+        argument: &'a KeywordArgument,
+        value: String,
+    },
+
+    /// A keyword argument given along with its value in one word, as in `--name=value`.
+    InlineKeywordArgument {
+        argument: &'a KeywordArgument,
+        value: String,
+    },
+
+    /// A word filling the next free positional argument.
+    PopulatedPositionalArgument {
+        argument: &'a PositionalArgument,
+        value: String,
+    },
+
+    /// A subcommand, after which only the arguments of the subcommand are valid.
+    Subcommand(&'a Subcommand),
+
+    /// The word being completed, which could become a keyword argument or a subcommand. Any
+    /// leading dashes are removed.
+    PartialKeywordArgument(String),
+
+    /// A word before the one being completed which matches no argument of the command.
+    Unknown(String),
+}
+
+impl<'a> Token<'a> {
+    /// The keyword argument given by this token, if any.
+    pub fn keyword_argument(&self) -> Option<&'a KeywordArgument> {
+        match self {
+            Token::Flag(argument)
+            | Token::PopulatedKeywordArgument { argument, .. }
+            | Token::InlineKeywordArgument { argument, .. } => Some(argument),
+            _ => None,
+        }
+    }
+
+    /// The name of the argument this token populates, if any.
+    fn argument_name(&self) -> Option<&'a str> {
+        match self {
+            Token::Flag(argument)
+            | Token::PopulatedKeywordArgument { argument, .. }
+            | Token::InlineKeywordArgument { argument, .. } => Some(&argument.name),
+            Token::PopulatedPositionalArgument { argument, .. } => Some(&argument.name),
+            _ => None,
+        }
+    }
+
+    /// Whether the argument this token populates and the given argument may not be used
+    /// together. Incompatibilities are treated as symmetric, so only one of the two arguments
+    /// needs to declare it.
+    fn is_incompatible_with(&self, other: &KeywordArgument) -> bool {
+        let incompatible_with = match self {
+            Token::Flag(argument)
+            | Token::PopulatedKeywordArgument { argument, .. }
+            | Token::InlineKeywordArgument { argument, .. } => &argument.incompatible_with,
+            Token::PopulatedPositionalArgument { argument, .. } => &argument.incompatible_with,
+            _ => return false,
+        };
+
+        self.argument_name().is_some_and(|name| {
+            incompatible_with.contains(&other.name)
+                || other.incompatible_with.iter().any(|other| other == name)
+        })
+    }
+}
+
+/// A token along with the indices of the words it was parsed from.
+#[derive(Debug)]
+pub struct ParsedToken<'a> {
+    pub token: Token<'a>,
+    pub words: Range<usize>,
+}
+
+/// A keyword argument which won't be offered as a candidate, and why.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Exclusion<'a> {
+    pub argument: &'a KeywordArgument,
+    pub reason: ExclusionReason,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExclusionReason {
+    /// The argument isn't repeatable and has already been given.
+    AlreadyUsed,
+
+    /// The argument may not be used together with the named argument, which has been given.
+    IncompatibleWith(String),
+}
+
+impl Display for ExclusionReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExclusionReason::AlreadyUsed => write!(f, "already used and not repeatable"),
+            ExclusionReason::IncompatibleWith(name) => write!(f, "incompatible with {name}"),
+        }
+    }
+}
+
+/// How a command line was understood, up to the word being completed.
+#[derive(Debug)]
+pub struct ParseState<'a> {
+    words: Vec<String>,
+    tokens: Vec<ParsedToken<'a>>,
+
+    /// The innermost command, i.e. the last subcommand given or the root command.
+    command: &'a Command,
+
+    /// The index of the innermost command, if it has been compiled.
+    index: Option<&'a CommandIndex>,
+
+    /// The index of the first token belonging to the innermost command.
+    scope_start: usize,
+
+    positional_argument_index: usize,
+}
+
+impl<'a> ParseState<'a> {
+    /// Parse words which have already been split, where the first is the name of the command and
+    /// the last is the word being completed.
+    pub(crate) fn new(
+        command: &'a Command,
+        index: Option<&'a CommandIndex>,
+        words: &[String],
+    ) -> Result<Self, CommandParseError> {
+        if words.is_empty() {
+            return Err(CommandParseError::InvalidCommandInput);
+        }
+
+        let started = Instant::now();
+        let mut state = ParseState {
+            words: words.to_vec(),
+            tokens: vec![],
+            command,
+            index,
+            scope_start: 0,
+            positional_argument_index: 0,
+        };
+
+        let mut index = 1;
+        while index < words.len() {
+            let word = &words[index];
+            let is_last = index + 1 == words.len();
+            let start = index;
+            index += 1;
+
+            let token = if let Some((argument, value)) = word
+                .starts_with('-')
+                .then(|| state.command.find_keyword_argument_with_value(word))
+                .flatten()
+            {
+                Token::InlineKeywordArgument {
+                    argument,
+                    value: value.to_string(),
+                }
+            } else if is_last && word.starts_with('-') {
+                Token::PartialKeywordArgument(word.trim_start_matches('-').to_string())
+            } else if let Some(argument) = state.command.find_keyword_argument(word) {
+                if argument.value_type == ValueType::Flag {
+                    Token::Flag(argument)
+                } else {
+                    index += 1;
+                    Token::PopulatedKeywordArgument {
+                        argument,
+                        value: words[start + 1].clone(),
+                    }
+                }
+            } else if let Some((position, subcommand)) = state
+                .command
+                .subcommands
+                .iter()
+                .enumerate()
+                .find(|(_, subcommand)| &subcommand.name == word)
+                .filter(|_| state.positional_argument_index == 0 && !is_last)
+            {
+                state.tokens.push(ParsedToken {
+                    token: Token::Subcommand(subcommand),
+                    words: start..index,
+                });
+                state.command = &subcommand.command;
+                state.index = state
+                    .index
+                    .and_then(|index| index.subcommand_indexes.get(position));
+                state.scope_start = state.tokens.len();
+                state.positional_argument_index = 0;
+                continue;
+            } else if word.len() > 1 && word.starts_with('-') {
+                Token::Unknown(word.clone())
+            } else if let Some(argument) = state
+                .command
+                .positional_arguments
+                .get(state.positional_argument_index)
+            {
+                state.positional_argument_index += 1;
+                Token::PopulatedPositionalArgument {
+                    argument,
+                    value: word.clone(),
+                }
+            } else if is_last {
+                Token::PartialKeywordArgument(word.clone())
+            } else {
+                Token::Unknown(word.clone())
+            };
+
+            state.tokens.push(ParsedToken {
+                token,
+                words: start..index,
+            });
+        }
+
+        log::debug!(
+            phase = "tokenise",
+            elapsed_us = started.elapsed().as_micros() as u64,
+            tokens:? = state.tokens;
+            "Finished phase"
+        );
+
+        Ok(state)
+    }
+
+    /// The words of the command line, starting with the name of the command.
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    /// Every token in the command line, including the subcommands.
+    pub fn tokens(&self) -> &[ParsedToken<'a>] {
+        &self.tokens
+    }
+
+    /// The command whose arguments are being completed, i.e. the last subcommand given or the
+    /// root command.
+    pub fn command(&self) -> &'a Command {
+        self.command
+    }
+
+    /// The tokens belonging to the command being completed.
+    pub fn current_tokens(&self) -> &[ParsedToken<'a>] {
+        &self.tokens[self.scope_start..]
+    }
+
+    /// The tokens belonging to the command being completed, apart from the one being completed.
+    fn previous_tokens(&self) -> &[ParsedToken<'a>] {
+        let current_tokens = self.current_tokens();
+        &current_tokens[..current_tokens.len().saturating_sub(1)]
+    }
+
+    /// The keyword arguments of the command being completed which can't be given again.
+    pub fn excluded_arguments(&self) -> Vec<Exclusion<'a>> {
+        self.command
+            .keyword_arguments
+            .iter()
+            .filter_map(|argument| {
+                self.get_exclusion_reason(argument)
+                    .map(|reason| Exclusion { argument, reason })
+            })
+            .collect()
+    }
+
+    fn get_exclusion_reason(&self, argument: &KeywordArgument) -> Option<ExclusionReason> {
+        let previous_tokens = self.previous_tokens();
+
+        if !argument.repeatable
+            && previous_tokens
+                .iter()
+                .any(|parsed| parsed.token.keyword_argument() == Some(argument))
+        {
+            return Some(ExclusionReason::AlreadyUsed);
+        }
+
+        previous_tokens
+            .iter()
+            .find(|parsed| parsed.token.is_incompatible_with(argument))
+            .and_then(|parsed| parsed.token.argument_name())
+            .map(|name| ExclusionReason::IncompatibleWith(name.to_string()))
+    }
+
+    /// Generate the candidates, along with their descriptions, for the word being completed.
+    pub fn candidates(&self) -> Result<Vec<Completion>, CommandParseError> {
+        self.candidates_in(Path::new("."))
+    }
+
+    /// Generate the candidates for the word being completed, completing relative paths from the
+    /// given directory rather than the working directory.
+    pub fn candidates_in(
+        &self,
+        working_directory: &Path,
+    ) -> Result<Vec<Completion>, CommandParseError> {
+        let started = Instant::now();
+
+        // Nothing follows the command name, so the cursor is still within it.
+        let Some(last_token) = self.current_tokens().last() else {
+            return Ok(vec![]);
+        };
+
+        let candidates = match &last_token.token {
+            Token::Flag(argument) => self.get_valid_keyword_arguments(&argument.name),
+            Token::PopulatedKeywordArgument { argument, value } => {
+                self.get_value_completions(argument, value, working_directory)?
+            }
+            Token::InlineKeywordArgument { argument, value } => self
+                .get_value_completions(argument, value, working_directory)?
+                .into_iter()
+                .map(|candidate| Completion {
+                    value: format!("{argument}={}", candidate.value),
+                    // The shell would treat the whole word as a path
+                    is_filename: false,
+                    ..candidate
+                })
+                .collect(),
+            Token::PopulatedPositionalArgument { argument, value } => {
+                let mut results = self.get_valid_keyword_arguments(value);
+
+                if self.positional_argument_index == 1 {
+                    results.extend(self.get_subcommand_completions(value));
+                }
+
+                match &argument.value_type {
+                    ValueType::Flag | ValueType::String => {}
+                    ValueType::Path => results.extend(
+                        self.command
+                            .get_path_completions(value, working_directory)?,
+                    ),
+                    ValueType::Enumeration(values) => {
+                        // The last token is always the most recently filled positional argument
+                        let index = self.index.and_then(|index| {
+                            index.positional_values[self.positional_argument_index - 1].as_ref()
+                        });
+                        results.extend(get_enumeration_completions(values, index, value))
+                    }
+                };
+
+                results
+            }
+            Token::PartialKeywordArgument(partial) => {
+                let mut results = self.get_valid_keyword_arguments(partial);
+
+                let is_dashed = self.words.last().is_some_and(|word| word.starts_with('-'));
+                if self.positional_argument_index == 0 && !is_dashed {
+                    results.extend(self.get_subcommand_completions(partial));
+                }
+
+                results
+            }
+            Token::Subcommand(_) | Token::Unknown(_) => vec![],
+        };
+
+        log::debug!(
+            phase = "candidates",
+            elapsed_us = started.elapsed().as_micros() as u64;
+            "Finished phase"
+        );
+        Ok(candidates)
+    }
+
+    /// Complete the value of a keyword argument.
+    fn get_value_completions(
+        &self,
+        argument: &KeywordArgument,
+        value: &str,
+        working_directory: &Path,
+    ) -> Result<Vec<Completion>, CommandParseError> {
+        Ok(match &argument.value_type {
+            ValueType::Flag | ValueType::String => vec![],
+            ValueType::Path => self
+                .command
+                .get_path_completions(value, working_directory)?,
+            ValueType::Enumeration(values) => {
+                let index = self
+                    .index
+                    .and_then(|index| index.keyword_values.get(&argument.name));
+                get_enumeration_completions(values, index, value)
+            }
+        })
+    }
+
+    /// Complete the keyword arguments which can still be given. Those in the standard style
+    /// which take a value are completed up to the `=` before it, without a trailing space, so
+    /// the value can be completed next.
+    fn get_valid_keyword_arguments(&self, query: &str) -> Vec<Completion> {
+        let index = self.index.map(|index| &index.keyword_arguments);
+
+        find_matching(&self.command.keyword_arguments, index, query, |argument| {
+            &argument.name
+        })
+        .into_iter()
+        .filter(|argument| self.get_exclusion_reason(argument).is_none())
+        .map(|argument| {
+            let takes_value = argument.value_type != ValueType::Flag;
+            match (&argument.style, takes_value) {
+                (KeywordArgumentStyle::Standard, true) => Completion {
+                    no_space: true,
+                    ..Completion::with_description(format!("{argument}="), &argument.description)
+                },
+                _ => Completion::with_description(argument.to_string(), &argument.description),
+            }
+        })
+        .collect()
+    }
+
+    fn get_subcommand_completions(&self, query: &str) -> Vec<Completion> {
+        let index = self.index.map(|index| &index.subcommands);
+
+        find_matching(&self.command.subcommands, index, query, |subcommand| {
+            &subcommand.name
+        })
+        .into_iter()
+        .map(|subcommand| {
+            Completion::with_description(&subcommand.name, &subcommand.command.description)
+        })
+        .collect()
+    }
+}
+
+fn get_enumeration_completions(
+    members: &[String],
+    index: Option<&PrefixIndex>,
+    query: &str,
+) -> Vec<Completion> {
+    find_matching(members, index, query, |member| member)
+        .into_iter()
+        .map(Completion::new)
+        .collect()
+}
+
+/// Find the items whose key starts with the query, in their original order, using the index of
+/// the keys if there is one.
+fn find_matching<'i, T>(
+    items: &'i [T],
+    index: Option<&PrefixIndex>,
+    query: &str,
+    key: impl Fn(&T) -> &str,
+) -> Vec<&'i T> {
+    match index {
+        Some(index) => index
+            .matching(query)
+            .into_iter()
+            .map(|position| &items[position])
+            .collect(),
+        None => items
+            .iter()
+            .filter(|item| key(item).starts_with(query))
+            .collect(),
+    }
+}
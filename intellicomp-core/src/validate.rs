@@ -0,0 +1,374 @@
+use std::ops::Range;
+
+use thiserror::Error;
+
+use crate::{
+    lexer::{split_words, Word},
+    Command, KeywordArgument, PositionalArgument, ValueType,
+};
+
+/// A command line which matches its command.
+#[derive(Debug)]
+pub struct ParsedCommand<'a> {
+    /// The name the command was given by, i.e. the first word of the line or the name of a
+    /// subcommand.
+    pub name: String,
+    pub command: &'a Command,
+    pub arguments: Vec<ParsedArgument<'a>>,
+
+    /// The subcommand given in place of the first positional argument, if any.
+    pub subcommand: Option<Box<ParsedCommand<'a>>>,
+
+    /// The span of the name within the line.
+    pub span: Range<usize>,
+}
+
+/// An argument given on a command line, along with the span of the words it came from.
+#[derive(Debug)]
+pub enum ParsedArgument<'a> {
+    Flag {
+        argument: &'a KeywordArgument,
+        span: Range<usize>,
+    },
+    Keyword {
+        argument: &'a KeywordArgument,
+        value: String,
+        span: Range<usize>,
+    },
+    Positional {
+        argument: &'a PositionalArgument,
+        value: String,
+        span: Range<usize>,
+    },
+}
+
+impl<'a> ParsedArgument<'a> {
+    pub fn span(&self) -> &Range<usize> {
+        match self {
+            ParsedArgument::Flag { span, .. }
+            | ParsedArgument::Keyword { span, .. }
+            | ParsedArgument::Positional { span, .. } => span,
+        }
+    }
+
+    /// The keyword argument given, if this isn't a positional argument.
+    pub fn keyword_argument(&self) -> Option<&'a KeywordArgument> {
+        match self {
+            ParsedArgument::Flag { argument, .. } | ParsedArgument::Keyword { argument, .. } => {
+                Some(argument)
+            }
+            ParsedArgument::Positional { .. } => None,
+        }
+    }
+
+    fn name(&self) -> &'a str {
+        match self {
+            ParsedArgument::Flag { argument, .. } | ParsedArgument::Keyword { argument, .. } => {
+                &argument.name
+            }
+            ParsedArgument::Positional { argument, .. } => &argument.name,
+        }
+    }
+
+    fn incompatible_with(&self) -> &'a [String] {
+        match self {
+            ParsedArgument::Flag { argument, .. } | ParsedArgument::Keyword { argument, .. } => {
+                &argument.incompatible_with
+            }
+            ParsedArgument::Positional { argument, .. } => &argument.incompatible_with,
+        }
+    }
+
+    /// The argument as it would be written in a message, e.g. `--verbose` or `file`.
+    fn display_name(&self) -> String {
+        match self.keyword_argument() {
+            Some(argument) => argument.to_string(),
+            None => self.name().to_string(),
+        }
+    }
+}
+
+/// A problem found with a command line, along with the span of the line it applies to.
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("{kind}")]
+pub struct ValidationError {
+    pub kind: ValidationErrorKind,
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ValidationErrorKind {
+    #[error("The command line is empty")]
+    EmptyCommand,
+
+    #[error("The command line ends within a quote or escape")]
+    UnterminatedWord,
+
+    #[error("Unknown option {0}")]
+    UnknownOption(String),
+
+    #[error("The argument {0} is missing a value")]
+    MissingValue(String),
+
+    #[error("{value} is not a valid value for {argument}, expected one of: {}", expected.join(", "))]
+    InvalidEnumMember {
+        argument: String,
+        value: String,
+        expected: Vec<String>,
+    },
+
+    #[error("{argument} can't be used together with {other}")]
+    IncompatibleArguments { argument: String, other: String },
+
+    #[error("{0} can only be given once")]
+    RepeatedArgument(String),
+
+    #[error("Unexpected positional argument {0}")]
+    TooManyPositionals(String),
+
+    #[error("The required argument {0} is missing")]
+    MissingRequiredArgument(String),
+}
+
+impl Command {
+    /// Parse a complete command line, the first word of which is the name of the command.
+    ///
+    /// Every problem with the line is returned rather than just the first, in the order they
+    /// appear.
+    pub fn parse(&self, line: &str) -> Result<ParsedCommand<'_>, Vec<ValidationError>> {
+        let words = split_words(line).map_err(|span| {
+            vec![ValidationError {
+                kind: ValidationErrorKind::UnterminatedWord,
+                span,
+            }]
+        })?;
+
+        let Some((name, arguments)) = words.split_first() else {
+            return Err(vec![ValidationError {
+                kind: ValidationErrorKind::EmptyCommand,
+                span: 0..line.len(),
+            }]);
+        };
+
+        let mut errors = vec![];
+        let parsed = self.parse_words(name, arguments, &mut errors);
+        errors.sort_by_key(|error| error.span.start);
+
+        if errors.is_empty() {
+            Ok(parsed)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Check a complete command line, returning every problem found with it.
+    pub fn validate(&self, line: &str) -> Vec<ValidationError> {
+        self.parse(line).err().unwrap_or_default()
+    }
+
+    fn parse_words(
+        &self,
+        name: &Word,
+        words: &[Word],
+        errors: &mut Vec<ValidationError>,
+    ) -> ParsedCommand<'_> {
+        let mut arguments = vec![];
+        let mut subcommand = None;
+        let mut positional_argument_index = 0;
+        let mut options_ended = false;
+
+        let mut index = 0;
+        while index < words.len() {
+            let word = &words[index];
+            index += 1;
+
+            if options_ended {
+                // Everything after `--` is positional, however it looks
+            } else if word.value == "--" {
+                options_ended = true;
+                continue;
+            } else if let Some((argument, value)) = self.split_keyword_value(word) {
+                check_value(&argument.to_string(), &argument.value_type, &value, errors);
+                arguments.push(ParsedArgument::Keyword {
+                    argument,
+                    value: value.value,
+                    span: word.span.clone(),
+                });
+                continue;
+            } else if let Some(argument) = self.find_keyword_argument(&word.value) {
+                if argument.value_type == ValueType::Flag {
+                    arguments.push(ParsedArgument::Flag {
+                        argument,
+                        span: word.span.clone(),
+                    });
+                } else if let Some(value) = words.get(index) {
+                    index += 1;
+                    check_value(&argument.to_string(), &argument.value_type, value, errors);
+                    arguments.push(ParsedArgument::Keyword {
+                        argument,
+                        value: value.value.clone(),
+                        span: word.span.start..value.span.end,
+                    });
+                } else {
+                    errors.push(ValidationError {
+                        kind: ValidationErrorKind::MissingValue(argument.to_string()),
+                        span: word.span.clone(),
+                    });
+                }
+                continue;
+            } else if let Some(found) = self
+                .subcommands
+                .iter()
+                .find(|subcommand| subcommand.name == word.value)
+                .filter(|_| positional_argument_index == 0)
+            {
+                subcommand = Some(Box::new(found.command.parse_words(
+                    word,
+                    &words[index..],
+                    errors,
+                )));
+                break;
+            } else if word.value.len() > 1 && word.value.starts_with('-') {
+                errors.push(ValidationError {
+                    kind: ValidationErrorKind::UnknownOption(word.value.clone()),
+                    span: word.span.clone(),
+                });
+                continue;
+            }
+
+            if let Some(argument) = self.positional_arguments.get(positional_argument_index) {
+                positional_argument_index += 1;
+                check_value(&argument.name, &argument.value_type, word, errors);
+                arguments.push(ParsedArgument::Positional {
+                    argument,
+                    value: word.value.clone(),
+                    span: word.span.clone(),
+                });
+            } else {
+                errors.push(ValidationError {
+                    kind: ValidationErrorKind::TooManyPositionals(word.value.clone()),
+                    span: word.span.clone(),
+                });
+            }
+        }
+
+        check_conflicts(&arguments, errors);
+
+        let missing_keyword_arguments = self.keyword_arguments.iter().filter(|argument| {
+            argument.required
+                && !arguments
+                    .iter()
+                    .any(|given| given.keyword_argument() == Some(*argument))
+        });
+        for argument in missing_keyword_arguments {
+            errors.push(ValidationError {
+                kind: ValidationErrorKind::MissingRequiredArgument(argument.to_string()),
+                span: name.span.clone(),
+            });
+        }
+
+        // A subcommand takes the place of the positional arguments
+        if subcommand.is_none() {
+            let missing_positional_arguments = self.positional_arguments
+                [positional_argument_index..]
+                .iter()
+                .filter(|argument| argument.required);
+            for argument in missing_positional_arguments {
+                errors.push(ValidationError {
+                    kind: ValidationErrorKind::MissingRequiredArgument(argument.name.clone()),
+                    span: name.span.clone(),
+                });
+            }
+        }
+
+        ParsedCommand {
+            name: name.value.clone(),
+            command: self,
+            arguments,
+            subcommand,
+            span: name.span.clone(),
+        }
+    }
+
+    /// Split a word of the form `--name=value` into the keyword argument it names and its value.
+    fn split_keyword_value(&self, word: &Word) -> Option<(&KeywordArgument, Word)> {
+        let (argument, value) = self.find_keyword_argument_with_value(&word.value)?;
+
+        // The value can only be pointed at exactly if the word wasn't quoted or escaped
+        let span = if word.span.len() == word.value.len() {
+            word.span.end - value.len()..word.span.end
+        } else {
+            word.span.clone()
+        };
+
+        Some((
+            argument,
+            Word {
+                value: value.to_string(),
+                span,
+            },
+        ))
+    }
+}
+
+fn check_value(
+    argument_name: &str,
+    value_type: &ValueType,
+    value: &Word,
+    errors: &mut Vec<ValidationError>,
+) {
+    if let ValueType::Enumeration(members) = value_type {
+        if !members.contains(&value.value) {
+            errors.push(ValidationError {
+                kind: ValidationErrorKind::InvalidEnumMember {
+                    argument: argument_name.to_string(),
+                    value: value.value.clone(),
+                    expected: members.clone(),
+                },
+                span: value.span.clone(),
+            });
+        }
+    }
+}
+
+/// Report arguments which are repeated despite not being repeatable, or which are incompatible
+/// with an argument given before them.
+fn check_conflicts(arguments: &[ParsedArgument], errors: &mut Vec<ValidationError>) {
+    for (index, argument) in arguments.iter().enumerate() {
+        let previous_arguments = &arguments[..index];
+
+        if let Some(keyword_argument) = argument.keyword_argument() {
+            if !keyword_argument.repeatable
+                && previous_arguments
+                    .iter()
+                    .any(|previous| previous.keyword_argument() == Some(keyword_argument))
+            {
+                errors.push(ValidationError {
+                    kind: ValidationErrorKind::RepeatedArgument(argument.display_name()),
+                    span: argument.span().clone(),
+                });
+                continue;
+            }
+        }
+
+        let incompatible = previous_arguments.iter().find(|previous| {
+            argument
+                .incompatible_with()
+                .iter()
+                .any(|name| name == previous.name())
+                || previous
+                    .incompatible_with()
+                    .iter()
+                    .any(|name| name == argument.name())
+        });
+        if let Some(previous) = incompatible {
+            errors.push(ValidationError {
+                kind: ValidationErrorKind::IncompatibleArguments {
+                    argument: argument.display_name(),
+                    other: previous.display_name(),
+                },
+                span: argument.span().clone(),
+            });
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// The environment variable holding the directory the embedded schemas are taken from, usually
+/// the `schemas` directory of an `intellicomp-schemas` checkout.
+const SNAPSHOT_VARIABLE: &str = "INTELLICOMP_SCHEMA_SNAPSHOT";
+
+fn main() {
+    println!("cargo:rerun-if-env-changed={SNAPSHOT_VARIABLE}");
+    if env::var_os("CARGO_FEATURE_EMBEDDED_SCHEMAS").is_none() {
+        return;
+    }
+
+    // Building with every feature shouldn't need a checkout of the schemas, so without one the
+    // snapshot is left empty, as if the feature were off
+    let schema_files = match env::var_os(SNAPSHOT_VARIABLE).map(PathBuf::from) {
+        Some(directory) => get_schema_files(&directory),
+        None => {
+            println!("cargo:warning={SNAPSHOT_VARIABLE} isn't set, so no schemas are embedded");
+            vec![]
+        }
+    };
+
+    let mut source = String::from("&[\n");
+    for schema_file in schema_files {
+        let path = schema_file.canonicalize().unwrap();
+        let file_name = schema_file.file_name().unwrap().to_str().unwrap();
+        source.push_str(&format!("    ({file_name:?}, include_bytes!({path:?})),\n"));
+    }
+    source.push(']');
+
+    let out_directory = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::write(out_directory.join("embedded_schemas.rs"), source).unwrap();
+}
+
+/// The schema files in the snapshot directory, in order of name.
+fn get_schema_files(directory: &Path) -> Vec<PathBuf> {
+    println!("cargo:rerun-if-changed={}", directory.display());
+
+    let mut schema_files = fs::read_dir(directory)
+        .unwrap_or_else(|error| panic!("Failed to read {}: {error}", directory.display()))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|e| e == "yaml"))
+        .collect::<Vec<_>>();
+    schema_files.sort();
+    schema_files
+}
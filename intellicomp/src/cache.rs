@@ -0,0 +1,669 @@
+use std::{
+    fs,
+    io::BufReader,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use directories::ProjectDirs;
+use intellicomp_core::{Command, CompiledCommand, Overlay};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hash_content, trust::is_project_schema, Config, IntellicompError, SchemaDirectoryKind,
+    SchemaSearchPath, SourceConfig, SourceList, TrustLevel, TrustStore,
+};
+
+/// Bumped whenever the meaning of a cache entry changes, so that entries written by older
+/// versions are recompiled rather than trusted.
+const CACHE_VERSION: u32 = 4;
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    version: u32,
+
+    /// The schema followed by its overlays.
+    sources: Vec<PathBuf>,
+
+    /// The modification times of the sources when they were compiled.
+    modified: Vec<SystemTime>,
+
+    /// The SHA-256 hashes of the sources when they were compiled, so that touching them doesn't
+    /// force a recompile.
+    hashes: Vec<String>,
+
+    command: CompiledCommand,
+}
+
+/// The files a command is compiled from: a schema and the overlays applied to it, in the order
+/// they're applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaSources {
+    pub schema: PathBuf,
+    pub overlays: Vec<PathBuf>,
+
+    /// The trust level of the source each file came from, starting with the schema.
+    pub trust_levels: Vec<TrustLevel>,
+
+    /// The schema sources whose signed manifests any files from them are checked against.
+    pub sources: Vec<SourceConfig>,
+}
+
+impl SchemaSources {
+    /// A schema along with the overlays for its command from the schema search path.
+    pub fn find(schema_file: &Path) -> Result<Self, IntellicompError> {
+        SchemaSources::find_from(schema_file, &std::env::current_dir().unwrap_or_default())
+    }
+
+    /// A schema along with the overlays for its command from the schema search path as seen from
+    /// the given directory, rather than the working directory.
+    pub fn find_from(
+        schema_file: &Path,
+        working_directory: &Path,
+    ) -> Result<Self, IntellicompError> {
+        let trust = TrustStore::load()?;
+        let sources = SourceList::load()?;
+        let search_path = SchemaSearchPath::for_working_directory(
+            working_directory,
+            &Config::load()?,
+            &trust,
+            &sources,
+        );
+
+        SchemaSources::find_in(schema_file, &search_path, &trust, sources)
+    }
+
+    /// A schema along with the overlays for its command from a search path.
+    ///
+    /// The schema is trusted as much as the directory it was found in, which is decided before
+    /// following any symlink so that a schema can't borrow the trust of wherever it points.
+    /// Schemas from a directory which isn't trusted are refused, as are those outside of every
+    /// schema directory.
+    pub fn find_in(
+        schema_file: &Path,
+        search_path: &SchemaSearchPath,
+        trust: &TrustStore,
+        sources: SourceList,
+    ) -> Result<Self, IntellicompError> {
+        let command_name = schema_file
+            .file_stem()
+            .unwrap_or_default()
+            .to_str()
+            .ok_or(IntellicompError::InvalidUnicodeInPath)?;
+        let schema_file = get_schema_location(schema_file)?;
+
+        // Projects other than the one containing the working directory aren't in the search path,
+        // but their schemas can still be completed from within them
+        let source_directory = schema_file.parent().unwrap_or(&schema_file);
+        let trust_level = match search_path.get_directory_of(&schema_file) {
+            Some(directory) => directory.trust_level,
+            None if is_project_schema(&schema_file) => {
+                trust.level(source_directory, SchemaDirectoryKind::Project)
+            }
+            None => {
+                return Err(IntellicompError::OutsideSearchPath(
+                    schema_file.display().to_string(),
+                ))
+            }
+        };
+        if trust_level == TrustLevel::Untrusted {
+            return Err(IntellicompError::UntrustedSource(
+                source_directory.display().to_string(),
+            ));
+        }
+
+        let (overlay_levels, overlays) = search_path
+            .find_overlays(command_name)
+            .into_iter()
+            .map(|(directory, path)| (directory.trust_level, path))
+            .unzip::<_, _, Vec<_>, _>();
+
+        Ok(SchemaSources {
+            schema: schema_file,
+            overlays,
+            trust_levels: std::iter::once(trust_level).chain(overlay_levels).collect(),
+            sources: sources.sources,
+        })
+    }
+
+    /// Load the compiled command through the default cache, or directly if there is no cache
+    /// directory.
+    pub fn load(&self) -> Result<CompiledCommand, IntellicompError> {
+        let trust = TrustStore::load()?;
+        match SpecCache::default_location() {
+            Some(cache) => cache.load(self, &trust),
+            None => {
+                let contents = self.read()?;
+                self.verify(&get_hashes(&contents), &trust)?;
+                self.compile(&contents)
+            }
+        }
+    }
+
+    fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        std::iter::once(&self.schema).chain(&self.overlays)
+    }
+
+    /// The modification times of the schema and its overlays, which change if any of them do.
+    pub fn modified(&self) -> Result<Vec<SystemTime>, IntellicompError> {
+        self.paths()
+            .map(|path| Ok(fs::metadata(path)?.modified()?))
+            .collect()
+    }
+
+    fn read(&self) -> Result<Vec<Vec<u8>>, IntellicompError> {
+        self.paths().map(|path| Ok(fs::read(path)?)).collect()
+    }
+
+    /// The SHA-256 hashes of the schema and its overlays.
+    pub(crate) fn hashes(&self) -> Result<Vec<String>, IntellicompError> {
+        Ok(get_hashes(&self.read()?))
+    }
+
+    /// Check the content of each file from a pinned source against what was approved, and of
+    /// each file from a signed source against its manifest.
+    pub(crate) fn verify(
+        &self,
+        hashes: &[String],
+        trust: &TrustStore,
+    ) -> Result<(), IntellicompError> {
+        for ((path, level), hash) in self.paths().zip(&self.trust_levels).zip(hashes) {
+            let directory = path.parent().unwrap_or(path).display().to_string();
+            match level {
+                TrustLevel::Trusted => {}
+                TrustLevel::Pinned if trust.approved_hash(path) == Some(hash.as_str()) => {}
+                TrustLevel::Pinned => {
+                    return Err(IntellicompError::HeldBack {
+                        path: path.display().to_string(),
+                        directory,
+                    });
+                }
+                TrustLevel::Untrusted => return Err(IntellicompError::UntrustedSource(directory)),
+            }
+        }
+
+        for source in self.sources.iter().map(SourceConfig::open) {
+            if !self.paths().any(|path| source.contains(path)) {
+                continue;
+            }
+
+            if let Some(manifest) = source.load_manifest()? {
+                for (path, hash) in self.paths().zip(hashes) {
+                    if manifest.covers(path) {
+                        manifest.check(path, hash)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse the schema, apply the overlays to it and compile the result.
+    fn compile(&self, contents: &[Vec<u8>]) -> Result<CompiledCommand, IntellicompError> {
+        let mut command: Command = serde_yaml::from_slice(&contents[0])?;
+        for (path, contents) in self.overlays.iter().zip(&contents[1..]) {
+            let overlay: Overlay = serde_yaml::from_slice(contents)?;
+            command
+                .apply_overlay(overlay)
+                .map_err(|error| IntellicompError::InvalidOverlay {
+                    path: path.display().to_string(),
+                    error,
+                })?;
+        }
+
+        Ok(command.compile()?)
+    }
+}
+
+/// Compiled schemas stored alongside the YAML they came from, so that completing doesn't need to
+/// parse and check YAML each time.
+pub struct SpecCache {
+    directory: PathBuf,
+}
+
+impl SpecCache {
+    pub fn new(directory: PathBuf) -> Self {
+        SpecCache { directory }
+    }
+
+    /// The cache within the user's cache directory.
+    pub fn default_location() -> Option<Self> {
+        let dirs = ProjectDirs::from("com", "dob9601", "intellicomp")?;
+        Some(SpecCache::new(dirs.cache_dir().join("specs")))
+    }
+
+    /// Load a schema with its overlays applied, compiling it and updating the cache only if any
+    /// of them have changed since it was last compiled.
+    ///
+    /// Every file is checked against the trust store and its source's manifest before it's used,
+    /// including unchanged ones, using the hashes recorded in the entry. Their trust level or the
+    /// approved hashes may have changed since the entry was written.
+    pub fn load(
+        &self,
+        sources: &SchemaSources,
+        trust: &TrustStore,
+    ) -> Result<CompiledCommand, IntellicompError> {
+        let modified = sources.modified()?;
+        let entry_file = self.get_entry_file(&sources.schema);
+
+        let entry = self.read_entry(&entry_file).filter(|entry| {
+            entry.version == CACHE_VERSION && entry.sources.iter().eq(sources.paths())
+        });
+        let entry = match entry {
+            Some(entry) if entry.modified == modified => {
+                sources.verify(&entry.hashes, trust)?;
+                return Ok(entry.command);
+            }
+            entry => entry,
+        };
+
+        let contents = sources.read()?;
+        let hashes = get_hashes(&contents);
+        sources.verify(&hashes, trust)?;
+
+        let command = match entry.filter(|entry| entry.hashes == hashes) {
+            Some(entry) => {
+                log::debug!(spec:% = sources.schema.display(); "Schema touched but unchanged");
+                entry.command
+            }
+            None => {
+                log::debug!(
+                    spec:% = sources.schema.display(),
+                    overlays = sources.overlays.len();
+                    "Compiling schema"
+                );
+                sources.compile(&contents)?
+            }
+        };
+
+        let entry = CacheEntry {
+            version: CACHE_VERSION,
+            sources: sources.paths().cloned().collect(),
+            modified,
+            hashes,
+            command,
+        };
+        // The cache is only an optimisation, so failing to write it shouldn't fail completion
+        if let Err(error) = self.write_entry(&entry_file, &entry) {
+            log::warn!("Failed to write to the spec cache: {error}");
+        }
+
+        Ok(entry.command)
+    }
+
+    fn get_entry_file(&self, schema_file: &Path) -> PathBuf {
+        let key = fnv1a(schema_file.as_os_str().as_encoded_bytes());
+        self.directory.join(format!("{key:016x}.cbor"))
+    }
+
+    /// Read an entry, treating one which can't be read as missing.
+    fn read_entry(&self, entry_file: &Path) -> Option<CacheEntry> {
+        let file = fs::File::open(entry_file).ok()?;
+        match ciborium::from_reader(BufReader::new(file)) {
+            Ok(entry) => Some(entry),
+            Err(error) => {
+                log::warn!(
+                    entry:% = entry_file.display();
+                    "Ignoring unreadable cache entry: {error}"
+                );
+                None
+            }
+        }
+    }
+
+    /// Write an entry, replacing the previous one in a single step so that concurrent readers
+    /// never see a partial entry.
+    fn write_entry(&self, entry_file: &Path, entry: &CacheEntry) -> Result<(), IntellicompError> {
+        fs::create_dir_all(&self.directory)?;
+
+        let mut contents = vec![];
+        ciborium::into_writer(entry, &mut contents)
+            .map_err(|error| IntellicompError::Cache(error.to_string()))?;
+
+        let temporary_file = entry_file.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&temporary_file, contents)?;
+        fs::rename(&temporary_file, entry_file)?;
+        Ok(())
+    }
+}
+
+/// Load a schema given by path, such as one being checked or exported, as it is rather than
+/// through the search path and the spec cache. The overlays for its command from the search path
+/// are only applied if asked for, and are checked against the trust store like when completing.
+pub fn load_schema_file(
+    schema_file: &Path,
+    apply_overlays: bool,
+) -> Result<CompiledCommand, IntellicompError> {
+    let mut sources = SchemaSources {
+        schema: schema_file.to_path_buf(),
+        overlays: vec![],
+        trust_levels: vec![TrustLevel::Trusted],
+        sources: vec![],
+    };
+    if !apply_overlays {
+        return sources.compile(&sources.read()?);
+    }
+
+    let command_name = schema_file
+        .file_stem()
+        .unwrap_or_default()
+        .to_str()
+        .ok_or(IntellicompError::InvalidUnicodeInPath)?;
+    let trust = TrustStore::load()?;
+    let source_list = SourceList::load()?;
+    let search_path = SchemaSearchPath::from_environment(&Config::load()?, &trust, &source_list);
+    for (directory, path) in search_path.find_overlays(command_name) {
+        sources.overlays.push(path);
+        sources.trust_levels.push(directory.trust_level);
+    }
+    sources.sources = source_list.sources;
+
+    let contents = sources.read()?;
+    sources.verify(&get_hashes(&contents), &trust)?;
+    sources.compile(&contents)
+}
+
+/// Where a schema was found, with the directory it's in made canonical like those in the search
+/// path but the schema itself left as it is, even if it's a symlink.
+fn get_schema_location(schema_file: &Path) -> Result<PathBuf, IntellicompError> {
+    let file_name = schema_file
+        .file_name()
+        .ok_or_else(|| IntellicompError::SchemaNotFound(schema_file.display().to_string()))?;
+    let directory = match schema_file.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+
+    let schema_file = fs::canonicalize(directory)?.join(file_name);
+    fs::metadata(&schema_file)?;
+    Ok(schema_file)
+}
+
+/// Load a compiled schema with the overlays for its command applied.
+pub fn load_compiled_schema(schema_file: &Path) -> Result<CompiledCommand, IntellicompError> {
+    SchemaSources::find(schema_file)?.load()
+}
+
+fn get_hashes(contents: &[Vec<u8>]) -> Vec<String> {
+    contents
+        .iter()
+        .map(|contents| hash_content(contents))
+        .collect()
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+
+/// A 64 bit FNV-1a hash, which is stable between releases unlike the standard library's.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    use super::{load_schema_file, SchemaSources, SpecCache};
+    use crate::{
+        hash_file, IntellicompError, SchemaDirectory, SchemaDirectoryKind, SchemaSearchPath,
+        SourceList, TrustLevel, TrustStore,
+    };
+
+    const SCHEMA: &str = "description: demo\nkeyword_arguments:\n  - name: format\n    description: output format\n    repeatable: false\n    style: Standard\n    value_type:\n      type: Enumeration\n      content: [json, yaml]\n";
+
+    fn get_values(cache: &SpecCache, sources: &SchemaSources, trust: &TrustStore) -> Vec<String> {
+        cache
+            .load(sources, trust)
+            .unwrap()
+            .generate_candidates("demo --format ", 14)
+            .unwrap()
+            .into_iter()
+            .map(|candidate| candidate.value)
+            .collect()
+    }
+
+    /// Write a file with a modification time in the future, since modification times may be too
+    /// coarse to notice a quick change.
+    fn write_later(path: &Path, contents: &str) {
+        std::fs::write(path, contents).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_cache_is_invalidated_by_changes() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let schema = directory.join("demo.yaml");
+        std::fs::write(&schema, SCHEMA).unwrap();
+        let mut sources = SchemaSources {
+            schema,
+            overlays: vec![],
+            trust_levels: vec![TrustLevel::Trusted],
+            sources: vec![],
+        };
+        let cache = SpecCache::new(directory.join("cache"));
+        let trust = TrustStore::default();
+
+        assert_eq!(get_values(&cache, &sources, &trust), vec!["json", "yaml"]);
+        assert_eq!(
+            std::fs::read_dir(directory.join("cache")).unwrap().count(),
+            1
+        );
+        assert_eq!(get_values(&cache, &sources, &trust), vec!["json", "yaml"]);
+
+        write_later(&sources.schema, &SCHEMA.replace("yaml]", "yaml, toml]"));
+        assert_eq!(
+            get_values(&cache, &sources, &trust),
+            vec!["json", "yaml", "toml"]
+        );
+
+        let overlay = directory.join("demo.overlay.yaml");
+        std::fs::write(
+            &overlay,
+            "modify_arguments:\n  - name: format\n    remove_values: [json]\n",
+        )
+        .unwrap();
+        sources.overlays.push(overlay.clone());
+        sources.trust_levels.push(TrustLevel::Trusted);
+        assert_eq!(get_values(&cache, &sources, &trust), vec!["yaml", "toml"]);
+
+        write_later(
+            &overlay,
+            "modify_arguments:\n  - name: format\n    add_values: [csv]\n",
+        );
+        assert_eq!(
+            get_values(&cache, &sources, &trust),
+            vec!["json", "yaml", "toml", "csv"]
+        );
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_schema_file_is_loaded_without_overlays() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-file-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let schema = directory.join("demo.yaml");
+        std::fs::write(&schema, SCHEMA).unwrap();
+        std::fs::write(
+            directory.join("demo.overlay.yaml"),
+            "modify_arguments:\n  - name: format\n    remove_values: [json]\n",
+        )
+        .unwrap();
+
+        let values = load_schema_file(&schema, false)
+            .unwrap()
+            .generate_candidates("demo --format ", 14)
+            .unwrap()
+            .into_iter()
+            .map(|candidate| candidate.value)
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec!["json", "yaml"]);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_inconsistent_schema_is_rejected() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-invalid-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let schema = directory.join("demo.yaml");
+        std::fs::write(
+            &schema,
+            format!("{SCHEMA}    incompatible_with: [missing]\n"),
+        )
+        .unwrap();
+        let sources = SchemaSources {
+            schema,
+            overlays: vec![],
+            trust_levels: vec![TrustLevel::Trusted],
+            sources: vec![],
+        };
+
+        let error = SpecCache::new(directory.join("cache"))
+            .load(&sources, &TrustStore::default())
+            .unwrap_err();
+        assert!(error.to_string().contains("missing"), "{error}");
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_changes_to_pinned_schemas_are_held_back() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-held-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let schema = directory.join("demo.yaml");
+        std::fs::write(&schema, SCHEMA).unwrap();
+        let sources = SchemaSources {
+            schema: schema.clone(),
+            overlays: vec![],
+            trust_levels: vec![TrustLevel::Pinned],
+            sources: vec![],
+        };
+        let cache = SpecCache::new(directory.join("cache"));
+
+        let mut trust = TrustStore::default();
+        assert!(matches!(
+            cache.load(&sources, &trust),
+            Err(IntellicompError::HeldBack { .. })
+        ));
+
+        trust.approve(schema.clone(), hash_file(&schema).unwrap());
+        assert_eq!(get_values(&cache, &sources, &trust), vec!["json", "yaml"]);
+
+        write_later(&schema, &SCHEMA.replace("yaml]", "yaml, toml]"));
+        assert!(matches!(
+            cache.load(&sources, &trust),
+            Err(IntellicompError::HeldBack { .. })
+        ));
+
+        trust.approve(schema.clone(), hash_file(&schema).unwrap());
+        assert_eq!(
+            get_values(&cache, &sources, &trust),
+            vec!["json", "yaml", "toml"]
+        );
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_cached_schemas_are_checked_again() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-recheck-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let schema = directory.join("demo.yaml");
+        std::fs::write(&schema, SCHEMA).unwrap();
+        let mut sources = SchemaSources {
+            schema: schema.clone(),
+            overlays: vec![],
+            trust_levels: vec![TrustLevel::Trusted],
+            sources: vec![],
+        };
+        let cache = SpecCache::new(directory.join("cache"));
+        let trust = TrustStore::default();
+        assert_eq!(get_values(&cache, &sources, &trust), vec!["json", "yaml"]);
+
+        // The entry was written while the schema was trusted, but it's now pinned and unapproved
+        sources.trust_levels = vec![TrustLevel::Pinned];
+        assert!(matches!(
+            cache.load(&sources, &trust),
+            Err(IntellicompError::HeldBack { .. })
+        ));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_untrusted_project_schemas_are_refused() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-untrusted-{}", std::process::id()));
+        let project = directory.join("project/.intellicomp");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::create_dir_all(directory.join("real")).unwrap();
+        std::fs::write(project.join("zzbar.yaml"), SCHEMA).unwrap();
+        std::fs::write(directory.join("real/zzfoo.yaml"), SCHEMA).unwrap();
+        // A symlink out of the project mustn't be trusted as wherever it points
+        std::os::unix::fs::symlink("../../real/zzfoo.yaml", project.join("zzfoo.yaml")).unwrap();
+        let project = project.canonicalize().unwrap();
+
+        let mut trust = TrustStore::default();
+        let find = |schema_file: &str, trust: &TrustStore, in_search_path: bool| {
+            let directories = match in_search_path {
+                true => vec![SchemaDirectory {
+                    kind: SchemaDirectoryKind::Project,
+                    path: project.clone(),
+                    trust_level: trust.level(&project, SchemaDirectoryKind::Project),
+                    source: None,
+                }],
+                false => vec![],
+            };
+            SchemaSources::find_in(
+                &project.join(schema_file),
+                &SchemaSearchPath::new(directories),
+                trust,
+                SourceList::default(),
+            )
+        };
+
+        for schema_file in ["zzbar.yaml", "zzfoo.yaml"] {
+            for in_search_path in [true, false] {
+                assert!(
+                    matches!(
+                        find(schema_file, &trust, in_search_path),
+                        Err(IntellicompError::UntrustedSource(_))
+                    ),
+                    "{schema_file} was loaded from an untrusted project"
+                );
+            }
+        }
+        assert!(matches!(
+            find("../../real/zzfoo.yaml", &trust, true),
+            Err(IntellicompError::OutsideSearchPath(_))
+        ));
+
+        trust.set_level(project.clone(), TrustLevel::Trusted);
+        let sources = find("zzfoo.yaml", &trust, true).unwrap();
+        assert_eq!(sources.schema, project.join("zzfoo.yaml"));
+        assert_eq!(sources.trust_levels, vec![TrustLevel::Trusted]);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -1,26 +1,97 @@
 use std::path::PathBuf;
 
-use clap::{Parser, ValueEnum};
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand, ValueEnum};
+
+use crate::ShellRegistry;
 
 #[derive(Debug, Parser)]
 pub enum Cli {
     Complete(CompleteArgs),
     Hook(HookArgs),
     Autogenerate(AutogenerateArgs),
+    Export(ExportArgs),
+    Explain(ExplainArgs),
+    Check(CheckArgs),
+
+    /// Serve completions from a long-running process which keeps schemas in memory.
+    Daemon,
+
+    /// Fetch the latest schemas from every schema source, fetching them for the first time if
+    /// they haven't been yet.
+    Update,
+
+    /// Show which schema is used for a command, out of those found in the schema directories.
+    Which(WhichArgs),
+
+    /// Trust a schema source, approving the current content of its schemas.
+    Trust(TrustArgs),
+
+    /// Stop loading the schemas from a schema source.
+    Untrust(UntrustArgs),
+
+    /// Manage the sources schemas are fetched from.
+    #[command(subcommand)]
+    Source(SourceCommand),
+}
+
+impl Cli {
+    /// Parse the command line arguments, accepting the name of any shell in the registry.
+    pub fn parse_with_registry(registry: &ShellRegistry) -> Self {
+        let cli = Cli::parse();
+
+        let shell = match &cli {
+            Cli::Complete(args) => Some(&args.shell),
+            Cli::Hook(args) => Some(&args.shell),
+            Cli::Export(args) => Some(&args.shell),
+            Cli::Autogenerate(_)
+            | Cli::Explain(_)
+            | Cli::Check(_)
+            | Cli::Daemon
+            | Cli::Update
+            | Cli::Which(_)
+            | Cli::Trust(_)
+            | Cli::Untrust(_)
+            | Cli::Source(_) => None,
+        };
+
+        if let Some(shell) = shell {
+            if registry.get(shell).is_none() {
+                let shells = registry
+                    .iter()
+                    .map(|shell| shell.name())
+                    .collect::<Vec<_>>();
+
+                Cli::command()
+                    .error(
+                        ErrorKind::InvalidValue,
+                        format!(
+                            "invalid value '{shell}' for '<SHELL>'\n  [possible values: {}]",
+                            shells.join(", ")
+                        ),
+                    )
+                    .exit();
+            }
+        }
+
+        cli
+    }
 }
 
 #[derive(Debug, Parser)]
 #[clap(trailing_var_arg = true)]
 pub struct CompleteArgs {
-    pub shell: Shell,
+    pub shell: String,
     pub schema: PathBuf,
 
-    _extra: Vec<String>,
+    /// Any additional arguments passed by the shell, such as the command line for shells that do
+    /// not expose it through the environment.
+    #[arg(allow_hyphen_values = true)]
+    pub extra: Vec<String>,
 }
 
 #[derive(Debug, Parser)]
 pub struct HookArgs {
-    pub shell: Shell,
+    pub shell: String,
 }
 
 #[derive(Debug, Parser)]
@@ -29,10 +100,109 @@ pub struct AutogenerateArgs {
     pub output_directory: PathBuf,
 }
 
-#[derive(Clone, Debug, ValueEnum)]
-pub enum Shell {
-    Bash,
-    Fish,
-    Zsh,
-    Csh,
+#[derive(Debug, Parser)]
+pub struct ExportArgs {
+    pub shell: String,
+    pub schema: PathBuf,
+
+    /// Apply the overlays for the command from the schema directories, as completion does.
+    #[arg(long)]
+    pub overlays: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct ExplainArgs {
+    pub schema: PathBuf,
+
+    /// The command line to explain, starting with the name of the command.
+    pub line: String,
+
+    /// The byte offset of the cursor within the line, which defaults to the end of the line.
+    #[arg(long)]
+    pub cursor: Option<usize>,
+
+    /// Apply the overlays for the command from the schema directories, as completion does.
+    #[arg(long)]
+    pub overlays: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct CheckArgs {
+    pub schema: PathBuf,
+
+    /// Apply the overlays for the command from the schema directories, as completion does.
+    #[arg(long)]
+    pub overlays: bool,
+
+    /// The arguments to check, as they would be passed to the command.
+    #[arg(last = true)]
+    pub arguments: Vec<String>,
+}
+
+#[derive(Debug, Parser)]
+pub struct WhichArgs {
+    pub command: String,
+
+    /// Also list the schemas which are shadowed by the one used.
+    #[arg(long)]
+    pub all: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct TrustArgs {
+    /// The source, either `project`, `user`, `community` or `system`, or a schema directory or a
+    /// directory within a project. Defaults to the project containing the working directory.
+    pub source: Option<String>,
+
+    /// Only load schemas while their content is what's approved now, holding back any changes
+    /// until they're approved again.
+    #[arg(long)]
+    pub pin: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct UntrustArgs {
+    /// The source, given in the same way as for `trust`.
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SourceCommand {
+    /// Add a schema source after the existing ones, fetching its schemas.
+    Add(SourceAddArgs),
+
+    /// Remove a schema source along with the schemas fetched from it.
+    Remove(SourceRemoveArgs),
+
+    /// List the schema sources in order of precedence.
+    List,
+}
+
+#[derive(Debug, Parser)]
+pub struct SourceAddArgs {
+    pub name: String,
+
+    /// A git URL, including `file://` URLs, a directory of schemas or a `.tar.gz` bundle of
+    /// schemas.
+    pub location: String,
+
+    /// How to fetch the schemas, if it can't be told from the location.
+    #[arg(long, value_enum)]
+    pub transport: Option<TransportKind>,
+
+    /// The ed25519 public key, as hex, which the source's manifest must be signed with.
+    #[arg(long)]
+    pub public_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum TransportKind {
+    Git,
+    Directory,
+    Archive,
+}
+
+#[derive(Debug, Parser)]
+pub struct SourceRemoveArgs {
+    pub name: String,
 }
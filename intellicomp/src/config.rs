@@ -0,0 +1,49 @@
+use std::{fs, io::ErrorKind, path::PathBuf};
+
+use directories::ProjectDirs;
+use serde::Deserialize;
+
+use crate::IntellicompError;
+
+/// User settings, read from `config.yaml` within the intellicomp config directory.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Update the community schema repository in the background when the hook runs, once this
+    /// many days have passed since it was last updated. Updates are only made by `update` if
+    /// this isn't set.
+    pub update_interval_days: Option<u64>,
+
+    /// Never touch the network, so the community schemas are neither cloned nor updated. Only
+    /// the embedded schemas and those already on disk are used.
+    pub offline: bool,
+
+    /// Further directories to look for schemas in, which take precedence over the user's own
+    /// schemas and the community schemas.
+    pub schema_directories: Vec<PathBuf>,
+}
+
+impl Config {
+    /// Read the config file, using the defaults if there isn't one.
+    pub fn load() -> Result<Self, IntellicompError> {
+        let Some(path) = get_config_path() else {
+            return Ok(Config::default());
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+            Err(error) => return Err(error.into()),
+        };
+
+        serde_yaml::from_str(&contents).map_err(|error| IntellicompError::InvalidConfig {
+            path: path.display().to_string(),
+            error,
+        })
+    }
+}
+
+pub fn get_config_path() -> Option<PathBuf> {
+    let dirs = ProjectDirs::from("com", "dob9601", "intellicomp")?;
+    Some(dirs.config_dir().join("config.yaml"))
+}
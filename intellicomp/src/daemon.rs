@@ -0,0 +1,301 @@
+use std::{
+    collections::HashMap,
+    fs::DirBuilder,
+    io::{BufRead, BufReader, Write},
+    os::unix::{
+        fs::{DirBuilderExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use directories::ProjectDirs;
+use intellicomp_core::{CompiledCommand, Completion};
+use serde::{Deserialize, Serialize};
+
+use crate::{CompletionRequest, IntellicompError, SchemaSources, TrustStore};
+
+/// How long a client waits for the daemon before completing in-process instead.
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DaemonRequest {
+    schema: PathBuf,
+
+    /// The directory of the client, which paths are completed relative to.
+    working_directory: PathBuf,
+
+    request: CompletionRequest,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum DaemonResponse {
+    Candidates(Vec<Completion>),
+    Error(String),
+}
+
+/// The per-user socket the daemon listens on, within the runtime directory if there is one.
+pub fn get_socket_path() -> Option<PathBuf> {
+    let dirs = ProjectDirs::from("com", "dob9601", "intellicomp")?;
+    Some(
+        dirs.runtime_dir()
+            .unwrap_or(dirs.cache_dir())
+            .join("daemon.sock"),
+    )
+}
+
+/// Ask the daemon listening on the socket for candidates.
+///
+/// Returns `None` if the daemon couldn't be reached, in which case the candidates should be
+/// generated in-process.
+pub fn request_candidates(
+    socket_path: &Path,
+    schema: &Path,
+    request: &CompletionRequest,
+) -> Option<Result<Vec<Completion>, IntellicompError>> {
+    // The daemon runs elsewhere, so the paths are resolved here
+    let request = DaemonRequest {
+        schema: std::fs::canonicalize(schema).ok()?,
+        working_directory: std::env::current_dir().ok()?,
+        request: request.clone(),
+    };
+
+    let mut stream = UnixStream::connect(socket_path).ok()?;
+    stream.set_read_timeout(Some(TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(TIMEOUT)).ok()?;
+
+    let mut message = serde_json::to_string(&request).ok()?;
+    message.push('\n');
+    stream.write_all(message.as_bytes()).ok()?;
+
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response).ok()?;
+
+    match serde_json::from_str(&response) {
+        Ok(DaemonResponse::Candidates(candidates)) => Some(Ok(candidates)),
+        Ok(DaemonResponse::Error(error)) => Some(Err(IntellicompError::Daemon(error))),
+        Err(error) => {
+            log::warn!("Unreadable response from the daemon: {error}");
+            None
+        }
+    }
+}
+
+/// Bind the socket for a daemon, replacing it if it was left behind by one which is no longer
+/// running.
+pub fn bind(socket_path: &Path) -> Result<UnixListener, IntellicompError> {
+    if socket_path.exists() {
+        if UnixStream::connect(socket_path).is_ok() {
+            return Err(IntellicompError::DaemonAlreadyRunning(
+                socket_path.display().to_string(),
+            ));
+        }
+        std::fs::remove_file(socket_path)?;
+    }
+
+    // The socket is only reachable by its owner from the moment it's bound, as the directory
+    // holding it is private
+    if let Some(parent) = socket_path.parent() {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(parent)?;
+        std::fs::set_permissions(parent, std::fs::Permissions::from_mode(0o700))?;
+    }
+
+    let listener = UnixListener::bind(socket_path)?;
+    std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Answers completion requests with schemas kept in memory, reloading a schema whenever its file
+/// or one of its overlays is modified.
+#[derive(Default)]
+pub struct Daemon {
+    schemas: Mutex<HashMap<Vec<PathBuf>, LoadedSchema>>,
+}
+
+/// A compiled schema along with the modification times and hashes of its files when it was
+/// loaded.
+struct LoadedSchema {
+    modified: Vec<SystemTime>,
+    hashes: Vec<String>,
+    schema: Arc<CompiledCommand>,
+}
+
+impl Daemon {
+    /// Handle connections until the listener fails, each in a thread of its own so that a slow
+    /// client doesn't hold up the others.
+    pub fn serve(&self, listener: UnixListener) {
+        std::thread::scope(|scope| {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        scope.spawn(move || {
+                            if let Err(error) = self.handle_connection(stream) {
+                                log::warn!("Failed to handle a connection: {error}");
+                            }
+                        });
+                    }
+                    Err(error) => log::warn!("Failed to accept a connection: {error}"),
+                }
+            }
+        });
+    }
+
+    fn handle_connection(&self, stream: UnixStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let mut message = String::new();
+        BufReader::new(&stream).read_line(&mut message)?;
+
+        let response = match serde_json::from_str::<DaemonRequest>(&message) {
+            Ok(request) => {
+                log::debug!(spec:% = request.schema.display(); "Received request");
+                match self.complete(&request) {
+                    Ok(candidates) => DaemonResponse::Candidates(candidates),
+                    Err(error) => DaemonResponse::Error(error.to_string()),
+                }
+            }
+            Err(error) => DaemonResponse::Error(format!("Invalid request: {error}")),
+        };
+
+        let mut response = serde_json::to_string(&response)?;
+        response.push('\n');
+        (&stream).write_all(response.as_bytes())
+    }
+
+    /// Complete a request as the client would, finding the schema and completing paths from the
+    /// client's directory.
+    fn complete(
+        &self,
+        request: &DaemonRequest,
+    ) -> Result<Vec<Completion>, Box<dyn std::error::Error>> {
+        let schema = self.load_schema(&request.schema, &request.working_directory)?;
+
+        Ok(request
+            .request
+            .generate_candidates_in(&schema, &request.working_directory)?)
+    }
+
+    fn load_schema(
+        &self,
+        schema_file: &Path,
+        working_directory: &Path,
+    ) -> Result<Arc<CompiledCommand>, IntellicompError> {
+        // The overlays depend on the client's project, so they're part of what is cached
+        let sources = SchemaSources::find_from(schema_file, working_directory)?;
+        let key = std::iter::once(sources.schema.clone())
+            .chain(sources.overlays.iter().cloned())
+            .collect::<Vec<_>>();
+        let modified = sources.modified()?;
+
+        // The trust store may have changed since the schema was loaded, so it's checked again
+        if let Some(loaded) = self.schemas.lock().unwrap().get(&key) {
+            if loaded.modified == modified {
+                sources.verify(&loaded.hashes, &TrustStore::load()?)?;
+                return Ok(Arc::clone(&loaded.schema));
+            }
+        }
+
+        // Loaded without holding the lock, so that other schemas are served meanwhile
+        log::debug!(spec:% = schema_file.display(); "Loading schema");
+        let hashes = sources.hashes()?;
+        let schema = Arc::new(sources.load()?);
+        self.schemas.lock().unwrap().insert(
+            key,
+            LoadedSchema {
+                modified,
+                hashes,
+                schema: Arc::clone(&schema),
+            },
+        );
+        Ok(schema)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::fs::PermissionsExt, os::unix::net::UnixStream, path::PathBuf};
+
+    use super::{bind, request_candidates, Daemon, DaemonRequest};
+    use crate::CompletionRequest;
+
+    #[test]
+    fn test_daemon_serves_candidates() {
+        let directory = std::env::temp_dir().join(format!("intellicomp-{}", std::process::id()));
+        let schema_directory = directory.join("schemas");
+        let working_directory = directory.join("project");
+        std::fs::create_dir_all(&schema_directory).unwrap();
+        std::fs::create_dir_all(&working_directory).unwrap();
+        std::fs::write(working_directory.join("output.json"), "").unwrap();
+
+        let schema = schema_directory.join("demo.yaml");
+        std::fs::write(
+            &schema,
+            "description: demo\nkeyword_arguments:\n  - name: format\n    description: output format\n    repeatable: false\n    style: Standard\n    value_type:\n      type: Enumeration\n      content: [json, yaml]\n  - name: output\n    description: output file\n    repeatable: false\n    style: Standard\n    value_type:\n      type: Path\n",
+        )
+        .unwrap();
+        // Schemas are only served from the search path
+        std::env::set_var("INTELLICOMP_SCHEMA_PATH", &schema_directory);
+
+        let socket_path = directory.join("daemon").join("daemon.sock");
+        let listener = bind(&socket_path).unwrap();
+        let mode = std::fs::metadata(socket_path.parent().unwrap())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+        std::thread::spawn(move || Daemon::default().serve(listener));
+
+        // A client which never sends its request doesn't hold up the others
+        let _stalled = UnixStream::connect(&socket_path).unwrap();
+
+        let request = CompletionRequest::Words(
+            ["demo", "--format", "j"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        );
+        let candidates = request_candidates(&socket_path, &schema, &request)
+            .expect("The daemon could not be reached")
+            .unwrap();
+
+        assert_eq!(
+            candidates
+                .into_iter()
+                .map(|candidate| candidate.value)
+                .collect::<Vec<_>>(),
+            vec!["json"]
+        );
+
+        let missing = request_candidates(&PathBuf::from("/nonexistent"), &schema, &request);
+        assert!(missing.is_none());
+
+        // Paths are completed from the client's directory, not the daemon's
+        let candidates = Daemon::default()
+            .complete(&DaemonRequest {
+                schema: schema.clone(),
+                working_directory: working_directory.clone(),
+                request: CompletionRequest::Words(
+                    ["demo", "--output", "out"]
+                        .into_iter()
+                        .map(String::from)
+                        .collect(),
+                ),
+            })
+            .unwrap();
+        assert_eq!(
+            candidates
+                .into_iter()
+                .map(|candidate| candidate.value)
+                .collect::<Vec<_>>(),
+            vec!["output.json"]
+        );
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
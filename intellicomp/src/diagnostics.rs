@@ -0,0 +1,111 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    str::FromStr,
+    sync::Mutex,
+    time::{Instant, SystemTime},
+};
+
+use directories::ProjectDirs;
+use log::{
+    kv::{self, VisitSource},
+    LevelFilter, Log, Metadata, Record,
+};
+
+/// The environment variable which sets the level of the diagnostic log, e.g. `debug`.
+const LOG_VARIABLE: &str = "INTELLICOMP_LOG";
+
+/// Writes log records to `intellicomp.log` in the data directory, one per line.
+///
+/// Completion output is read by the shell and anything written to stderr lands in the middle of
+/// the user's command line, so diagnostics are kept out of the terminal entirely.
+struct FileLogger {
+    file: Mutex<File>,
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let mut line = format!(
+            "{}.{:06} {:<5} {}: {}",
+            timestamp.as_secs(),
+            timestamp.subsec_micros(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        let _ = record.key_values().visit(&mut KeyValueWriter(&mut line));
+
+        // Failing to log must not break completion, so errors here are ignored.
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(file, "{line}");
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Appends each key-value pair of a record as ` key=value`.
+struct KeyValueWriter<'a>(&'a mut String);
+
+impl<'kvs> VisitSource<'kvs> for KeyValueWriter<'_> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.push_str(&format!(" {key}={value}"));
+        Ok(())
+    }
+}
+
+/// Start writing diagnostics to the log file at the level given by `INTELLICOMP_LOG`. Nothing is
+/// logged if it is unset or isn't a valid level.
+pub fn init_logging() {
+    let level = std::env::var(LOG_VARIABLE)
+        .ok()
+        .and_then(|level| LevelFilter::from_str(&level).ok())
+        .unwrap_or(LevelFilter::Off);
+    if level == LevelFilter::Off {
+        return;
+    }
+
+    let Some(dirs) = ProjectDirs::from("com", "dob9601", "intellicomp") else {
+        return;
+    };
+    let _ = std::fs::create_dir_all(dirs.data_dir());
+    let Ok(file) = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dirs.data_dir().join("intellicomp.log"))
+    else {
+        return;
+    };
+
+    if log::set_boxed_logger(Box::new(FileLogger {
+        file: Mutex::new(file),
+    }))
+    .is_ok()
+    {
+        log::set_max_level(level);
+    }
+}
+
+/// Run one phase of completion, logging how long it took.
+pub fn timed<T>(phase: &str, run: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = run();
+    log::debug!(phase, elapsed_us = started.elapsed().as_micros() as u64; "Finished phase");
+    result
+}
@@ -0,0 +1,172 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use directories::ProjectDirs;
+use sha2::{Digest, Sha256};
+
+use crate::{repository::UpdateLock, source::remove_if_exists, IntellicompError};
+
+/// The schemas built into the binary with the `embedded-schemas` feature, by file name.
+#[cfg(feature = "embedded-schemas")]
+const EMBEDDED_SCHEMAS: &[(&str, &[u8])] =
+    include!(concat!(env!("OUT_DIR"), "/embedded_schemas.rs"));
+
+#[cfg(not(feature = "embedded-schemas"))]
+const EMBEDDED_SCHEMAS: &[(&str, &[u8])] = &[];
+
+/// The file recording which snapshot the embedded schema directory was written from.
+const SNAPSHOT_FILE_NAME: &str = ".snapshot";
+
+/// The directory the embedded schemas are written to, so that they're found like any others, or
+/// none if this build has no embedded schemas.
+pub fn get_embedded_schema_directory() -> Option<PathBuf> {
+    if EMBEDDED_SCHEMAS.is_empty() {
+        return None;
+    }
+
+    let dirs = ProjectDirs::from("com", "dob9601", "intellicomp")?;
+    Some(dirs.cache_dir().join("embedded-schemas"))
+}
+
+/// Write out the embedded schemas, unless the ones from this build already have been.
+pub fn extract_embedded_schemas() -> Result<(), IntellicompError> {
+    match get_embedded_schema_directory() {
+        Some(directory) => extract_schemas(&directory, EMBEDDED_SCHEMAS),
+        None => Ok(()),
+    }
+}
+
+/// Write schemas to a directory, replacing whatever was there unless it was written from the
+/// same snapshot. Several shells may start at once after an upgrade, so the schemas are written
+/// to a directory of their own which is then swapped into place, and only one shell does so.
+fn extract_schemas(directory: &Path, schemas: &[(&str, &[u8])]) -> Result<(), IntellicompError> {
+    let snapshot = get_snapshot_hash(schemas);
+    if is_extracted(directory, &snapshot) {
+        return Ok(());
+    }
+
+    if let Some(parent) = directory.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let _lock = match UpdateLock::acquire(directory.with_extension("lock")) {
+        Ok(lock) => lock,
+        // Another shell is writing the same schemas, which are used once it's done
+        Err(IntellicompError::UpdateInProgress) => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    if is_extracted(directory, &snapshot) {
+        return Ok(());
+    }
+
+    let temporary_directory = directory.with_extension(format!("{}.tmp", std::process::id()));
+    remove_if_exists(&temporary_directory)?;
+    let result = write_schemas(&temporary_directory, schemas, &snapshot);
+    if let Err(error) = result {
+        remove_if_exists(&temporary_directory)?;
+        return Err(error);
+    }
+
+    // Schemas which were dropped from the snapshot go along with the old directory
+    let old_directory = directory.with_extension(format!("{}.old", std::process::id()));
+    match fs::rename(directory, &old_directory) {
+        Err(error) if error.kind() != ErrorKind::NotFound => return Err(error.into()),
+        _ => {}
+    }
+    fs::rename(&temporary_directory, directory)?;
+    remove_if_exists(&old_directory)?;
+
+    log::info!(directory:% = directory.display(); "Wrote the embedded schemas");
+    Ok(())
+}
+
+fn is_extracted(directory: &Path, snapshot: &str) -> bool {
+    fs::read_to_string(directory.join(SNAPSHOT_FILE_NAME))
+        .is_ok_and(|existing| existing == snapshot)
+}
+
+fn write_schemas(
+    directory: &Path,
+    schemas: &[(&str, &[u8])],
+    snapshot: &str,
+) -> Result<(), IntellicompError> {
+    fs::create_dir_all(directory)?;
+    for (file_name, contents) in schemas {
+        fs::write(directory.join(file_name), contents)?;
+    }
+    fs::write(directory.join(SNAPSHOT_FILE_NAME), snapshot)?;
+    Ok(())
+}
+
+/// A hash of every embedded schema along with its name.
+fn get_snapshot_hash(schemas: &[(&str, &[u8])]) -> String {
+    let mut hasher = Sha256::new();
+    for (file_name, contents) in schemas {
+        for bytes in [file_name.as_bytes(), contents] {
+            hasher.update(bytes.len().to_le_bytes());
+            hasher.update(bytes);
+        }
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Barrier, thread};
+
+    use super::{extract_schemas, SNAPSHOT_FILE_NAME};
+
+    #[test]
+    fn test_schemas_are_replaced_by_a_new_snapshot() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-embedded-{}", std::process::id()));
+        let schemas = directory.join("embedded-schemas");
+
+        extract_schemas(&schemas, &[("git.yaml", b"old"), ("ls.yaml", b"dropped")]).unwrap();
+        assert_eq!(std::fs::read(schemas.join("ls.yaml")).unwrap(), b"dropped");
+
+        // The same snapshot is left alone
+        std::fs::write(schemas.join("git.yaml"), "edited").unwrap();
+        extract_schemas(&schemas, &[("git.yaml", b"old"), ("ls.yaml", b"dropped")]).unwrap();
+        assert_eq!(std::fs::read(schemas.join("git.yaml")).unwrap(), b"edited");
+
+        let snapshot = std::fs::read(schemas.join(SNAPSHOT_FILE_NAME)).unwrap();
+        extract_schemas(&schemas, &[("git.yaml", b"new")]).unwrap();
+        assert_eq!(std::fs::read(schemas.join("git.yaml")).unwrap(), b"new");
+        assert!(!schemas.join("ls.yaml").exists());
+        assert_ne!(
+            std::fs::read(schemas.join(SNAPSHOT_FILE_NAME)).unwrap(),
+            snapshot
+        );
+
+        // Only the schema directory itself is left behind
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_extractions_never_fail() {
+        let directory = std::env::temp_dir().join(format!(
+            "intellicomp-embedded-concurrent-{}",
+            std::process::id()
+        ));
+        let schemas = directory.join("embedded-schemas");
+        extract_schemas(&schemas, &[("git.yaml", b"old")]).unwrap();
+
+        let barrier = Barrier::new(4);
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    barrier.wait();
+                    extract_schemas(&schemas, &[("git.yaml", b"new")]).unwrap();
+                });
+            }
+        });
+        assert_eq!(std::fs::read(schemas.join("git.yaml")).unwrap(), b"new");
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
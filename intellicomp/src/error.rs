@@ -11,6 +11,87 @@ pub enum IntellicompError {
     #[error("IO Error: {0}")]
     FailedToReadSchema(#[from] serde_yaml::Error),
 
-    #[error("Failed to clone schemas repo: {0}")]
+    #[error("The schema is inconsistent: {0}")]
+    InvalidSchema(#[from] intellicomp_core::SchemaError),
+
+    #[error("Failed to apply the overlay {path}: {error}")]
+    InvalidOverlay {
+        path: String,
+        error: intellicomp_core::OverlayError,
+    },
+
+    #[error("Failed to use the spec cache: {0}")]
+    Cache(String),
+
+    #[error("Failed to clone or update the schemas repo: {0}")]
     FailedToCloneSchemaRepo(#[from] git2::Error),
+
+    #[error("The schema repository can't be fast-forwarded to the remote {0} branch")]
+    CannotFastForward(String),
+
+    #[error("The schema repository is already being updated")]
+    UpdateInProgress,
+
+    #[error("The schemas in {0} aren't signed, so none of them are used")]
+    UnsignedSchemas(String),
+
+    #[error("The schema manifest in {0} isn't signed by the expected key, so none of its schemas are used")]
+    InvalidSignature(String),
+
+    #[error("{0} doesn't match the signed schema manifest, so it isn't used")]
+    TamperedSchema(String),
+
+    #[error("Invalid config file {path}: {error}")]
+    InvalidConfig {
+        path: String,
+        error: serde_yaml::Error,
+    },
+
+    #[error("The schemas in {0} aren't trusted, which can be changed with `intellicomp trust`")]
+    UntrustedSource(String),
+
+    #[error("{0} isn't in a schema directory, so it isn't trusted")]
+    OutsideSearchPath(String),
+
+    #[error("{path} changed since it was approved, review it and run `intellicomp trust --pin {directory}`")]
+    HeldBack { path: String, directory: String },
+
+    #[error("No schema source named {0} was found")]
+    UnknownSource(String),
+
+    #[error("There's already a schema source named {0}")]
+    SourceExists(String),
+
+    #[error("Invalid schema source name {0}, which may only contain letters, digits, - and _")]
+    InvalidSourceName(String),
+
+    #[error("Invalid ed25519 public key {0}, which should be 64 hex digits")]
+    InvalidPublicKey(String),
+
+    #[error("The schema directory {0} doesn't exist")]
+    MissingSchemaDirectory(String),
+
+    #[error("No project schema directory was found in {0} or its parents")]
+    ProjectNotFound(String),
+
+    #[error("No schema was found for {0}")]
+    SchemaNotFound(String),
+
+    #[error("Exporting standalone completion scripts is not supported for {0}")]
+    ExportNotSupported(String),
+
+    #[error("No shell named {0} has been registered")]
+    UnknownShell(String),
+
+    #[error("Invalid completion request: {0}")]
+    InvalidCompletionRequest(&'static str),
+
+    #[error("The completion daemon failed: {0}")]
+    Daemon(String),
+
+    #[error("The completion daemon is already running at {0}")]
+    DaemonAlreadyRunning(String),
+
+    #[error("The completion daemon is not supported on this platform")]
+    DaemonNotSupported,
 }
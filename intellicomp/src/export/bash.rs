@@ -0,0 +1,413 @@
+use intellicomp_core::{Command, ValueType};
+
+use super::{collect_state, get_excluding_spellings, get_scopes, get_spellings};
+use crate::{Bash, ExportableShell};
+
+impl ExportableShell for Bash {
+    fn export_completions(&self, command_name: &str, command: &Command) -> String {
+        let function_name = format!(
+            "_intellicomp_{}",
+            command_name.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+        );
+
+        let mut value_options = vec![];
+        let mut transitions = vec![];
+        collect_state(command, &[], &mut value_options, &mut transitions);
+
+        let scopes = get_scopes(command, vec![]);
+
+        let mut script = vec![
+            format!("# Completions for {command_name}, generated by intellicomp"),
+            String::new(),
+            generate_helper_functions(&function_name),
+            format!("{function_name}() {{"),
+            "    local cur=\"${COMP_WORDS[COMP_CWORD]}\"".to_string(),
+            "    local scope='' position=0 value_option='' used=() word i".to_string(),
+            "    COMPREPLY=()".to_string(),
+            String::new(),
+        ];
+        script.extend(generate_state_loop(&value_options, &transitions));
+        script.extend(generate_value_completions(&function_name, &scopes));
+        script.extend(generate_keyword_completions(&function_name, &scopes));
+        script.extend(generate_positional_completions(&function_name, &scopes));
+        script.extend([
+            "}".to_string(),
+            String::new(),
+            format!("complete -F {function_name} {}", quote(command_name)),
+        ]);
+
+        script.join("\n") + "\n"
+    }
+}
+
+fn generate_helper_functions(function_name: &str) -> String {
+    format!(
+        r#"# Succeeds if none of the given options appear before the cursor
+{function_name}_unused() {{
+    local option word
+    for option in "$@"; do
+        for word in "${{used[@]}}"; do
+            [[ "$word" == "$option" ]] && return 1
+        done
+    done
+    return 0
+}}
+
+# Offers each of the given candidates which start with the word being completed
+{function_name}_filter() {{
+    local candidate
+    for candidate in "$@"; do
+        [[ "$candidate" == "$cur"* ]] && COMPREPLY+=("$candidate")
+    done
+}}
+
+# Offers the paths which start with the word being completed
+{function_name}_paths() {{
+    local path
+    compopt -o filenames 2>/dev/null
+    while IFS= read -r path; do
+        COMPREPLY+=("$path")
+    done < <(compgen -f -- "$cur")
+}}
+"#
+    )
+}
+
+/// Generate a loop over the words before the cursor which determines the subcommand path, the
+/// number of positional arguments given to it, the options used so far and whether the word
+/// being completed is the value of an option.
+fn generate_state_loop(value_options: &[String], transitions: &[(String, String)]) -> Vec<String> {
+    let mut lines = vec![
+        "    for ((i = 1; i < COMP_CWORD; i++)); do".to_string(),
+        "        word=\"${COMP_WORDS[i]}\"".to_string(),
+        "        if [[ -n \"$value_option\" ]]; then".to_string(),
+        "            value_option=''".to_string(),
+        "            continue".to_string(),
+        "        fi".to_string(),
+        "        case \"$word\" in".to_string(),
+    ];
+
+    if !value_options.is_empty() {
+        lines.extend([
+            format!(
+                "            {})",
+                value_options
+                    .iter()
+                    .map(|option| quote(option))
+                    .collect::<Vec<_>>()
+                    .join("|")
+            ),
+            "                value_option=\"$word\"".to_string(),
+            "                used+=(\"$word\")".to_string(),
+            "                ;;".to_string(),
+        ]);
+    }
+
+    lines.extend([
+        "            -*)".to_string(),
+        "                used+=(\"$word\")".to_string(),
+        "                ;;".to_string(),
+        "            *)".to_string(),
+    ]);
+
+    if !transitions.is_empty() {
+        lines.push("                if ((position == 0)); then".to_string());
+        lines.push("                    case \"$scope:$word\" in".to_string());
+        for (transition, scope) in transitions {
+            lines.push(format!(
+                "                        {}) scope={}; continue ;;",
+                quote(transition),
+                quote(scope)
+            ));
+        }
+        lines.push("                    esac".to_string());
+        lines.push("                fi".to_string());
+    }
+
+    lines.extend([
+        "                position=$((position + 1))".to_string(),
+        "                ;;".to_string(),
+        "        esac".to_string(),
+        "    done".to_string(),
+        String::new(),
+    ]);
+
+    lines
+}
+
+fn generate_value_completions(
+    function_name: &str,
+    scopes: &[(Vec<&str>, &Command)],
+) -> Vec<String> {
+    let mut arms = vec![];
+    for (path, command) in scopes {
+        for argument in &command.keyword_arguments {
+            if let Some(completion) = get_value_completion(function_name, &argument.value_type) {
+                let patterns = get_spellings(argument)
+                    .iter()
+                    .map(|spelling| quote(&format!("{}:{spelling}", path.join(" "))))
+                    .collect::<Vec<_>>();
+
+                arms.push(format!("            {})", patterns.join("|")));
+                arms.push(format!("                {completion}"));
+                arms.push("                ;;".to_string());
+            }
+        }
+    }
+
+    let mut lines = vec!["    if [[ -n \"$value_option\" ]]; then".to_string()];
+    if !arms.is_empty() {
+        lines.push("        case \"$scope:$value_option\" in".to_string());
+        lines.extend(arms);
+        lines.push("        esac".to_string());
+    }
+    lines.extend([
+        "        return 0".to_string(),
+        "    fi".to_string(),
+        String::new(),
+    ]);
+
+    lines
+}
+
+fn generate_keyword_completions(
+    function_name: &str,
+    scopes: &[(Vec<&str>, &Command)],
+) -> Vec<String> {
+    let mut arms = vec![];
+    for (path, command) in scopes {
+        if command.keyword_arguments.is_empty() {
+            continue;
+        }
+
+        arms.push(format!("        {})", quote(&path.join(" "))));
+        for argument in &command.keyword_arguments {
+            let excluded_spellings = get_excluding_spellings(argument, command);
+            let filter = format!("{function_name}_filter {}", quote(&argument.to_string()));
+
+            if excluded_spellings.is_empty() {
+                arms.push(format!("            {filter}"));
+            } else {
+                arms.push(format!(
+                    "            {function_name}_unused {} && {filter}",
+                    excluded_spellings
+                        .iter()
+                        .map(|spelling| quote(spelling))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                ));
+            }
+        }
+        arms.push("            ;;".to_string());
+    }
+
+    if arms.is_empty() {
+        return vec![];
+    }
+
+    let mut lines = vec!["    case \"$scope\" in".to_string()];
+    lines.extend(arms);
+    lines.extend(["    esac".to_string(), String::new()]);
+    lines
+}
+
+fn generate_positional_completions(
+    function_name: &str,
+    scopes: &[(Vec<&str>, &Command)],
+) -> Vec<String> {
+    let mut arms = vec![];
+    for (path, command) in scopes {
+        let scope = path.join(" ");
+
+        let mut first_position = vec![];
+        if !command.subcommands.is_empty() {
+            first_position.push(format!(
+                "{function_name}_filter {}",
+                command
+                    .subcommands
+                    .iter()
+                    .map(|subcommand| quote(&subcommand.name))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ));
+        }
+
+        for (position, argument) in command.positional_arguments.iter().enumerate() {
+            let mut completions = if position == 0 {
+                std::mem::take(&mut first_position)
+            } else {
+                vec![]
+            };
+            completions.extend(get_value_completion(function_name, &argument.value_type));
+
+            if !completions.is_empty() {
+                arms.push(format!(
+                    "        {})",
+                    quote(&format!("{scope}:{position}"))
+                ));
+                arms.extend(
+                    completions
+                        .into_iter()
+                        .map(|completion| format!("            {completion}")),
+                );
+                arms.push("            ;;".to_string());
+            }
+        }
+
+        if !first_position.is_empty() {
+            arms.push(format!("        {})", quote(&format!("{scope}:0"))));
+            arms.extend(
+                first_position
+                    .into_iter()
+                    .map(|completion| format!("            {completion}")),
+            );
+            arms.push("            ;;".to_string());
+        }
+    }
+
+    if arms.is_empty() {
+        return vec![];
+    }
+
+    let mut lines = vec!["    case \"$scope:$position\" in".to_string()];
+    lines.extend(arms);
+    lines.push("    esac".to_string());
+    lines
+}
+
+/// Get the command which offers the possible values of an argument, or `None` if they can't be
+/// completed.
+fn get_value_completion(function_name: &str, value_type: &ValueType) -> Option<String> {
+    match value_type {
+        ValueType::Path => Some(format!("{function_name}_paths")),
+        ValueType::Enumeration(values) => Some(format!(
+            "{function_name}_filter {}",
+            values
+                .iter()
+                .map(|value| quote(value))
+                .collect::<Vec<_>>()
+                .join(" ")
+        )),
+        _ => None,
+    }
+}
+
+/// Quote a string for use as a single bash word, leaving it bare where that is unambiguous.
+fn quote(string: &str) -> String {
+    if !string.is_empty()
+        && string
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=+,@%:".contains(c))
+    {
+        string.to_string()
+    } else {
+        format!("'{}'", string.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use crate::{export::tests::load_test_schema, Bash, ExportableShell};
+
+    const GOLDEN_SCRIPT: &str = include_str!("testdata/vcs.bash");
+
+    /// Run the exported script in bash for a command line split into words, the last of which is
+    /// being completed. Returns `None` if bash isn't installed.
+    fn complete_with_script(words: &[&str]) -> Option<Vec<String>> {
+        let words = words
+            .iter()
+            .map(|word| super::quote(word))
+            .collect::<Vec<_>>();
+        let script = format!(
+            "{GOLDEN_SCRIPT}\nCOMP_WORDS=({})\nCOMP_CWORD={}\n_intellicomp_vcs\nprintf '%s\\n' \"${{COMPREPLY[@]}}\"",
+            words.join(" "),
+            words.len() - 1
+        );
+
+        let output = Command::new("bash").arg("-c").arg(script).output().ok()?;
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Some(
+            String::from_utf8(output.stdout)
+                .unwrap()
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(String::from)
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_export_matches_golden_script() {
+        let schema = load_test_schema();
+        assert_eq!(
+            Bash.export_completions("vcs", schema.command()),
+            GOLDEN_SCRIPT
+        );
+    }
+
+    #[test]
+    fn test_exported_script_is_valid_bash() {
+        let Ok(output) = Command::new("bash")
+            .arg("-n")
+            .arg(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/export/testdata/vcs.bash"
+            ))
+            .output()
+        else {
+            return;
+        };
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    #[test]
+    fn test_exported_script_completes() {
+        let Some(candidates) = complete_with_script(&["vcs", ""]) else {
+            return;
+        };
+        assert_eq!(
+            candidates,
+            vec![
+                "--verbose",
+                "--quiet",
+                "--config-file",
+                "--message",
+                "--color",
+                "remote",
+                "apply"
+            ]
+        );
+
+        // Incompatible and used arguments are left out
+        assert_eq!(
+            complete_with_script(&["vcs", "--quiet", "--"]).unwrap(),
+            vec!["--config-file", "--message", "--color"]
+        );
+        assert_eq!(
+            complete_with_script(&["vcs", "-v", "--color", "a"]).unwrap(),
+            vec!["always", "auto"]
+        );
+        assert_eq!(
+            complete_with_script(&["vcs", "remote", "-protocol", "ssh", ""]).unwrap(),
+            vec!["add"]
+        );
+        assert_eq!(
+            complete_with_script(&["vcs", "remote", "add", "upstream", ""]).unwrap(),
+            vec!["origin's", "file://"]
+        );
+        // Free text values have nothing to offer
+        assert!(complete_with_script(&["vcs", "--message", ""])
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+use intellicomp_core::{Command, KeywordArgumentStyle, ValueType};
+
+use super::{collect_state, get_excluding_spellings};
+use crate::{ExportableShell, Fish};
+
+impl ExportableShell for Fish {
+    fn export_completions(&self, command_name: &str, command: &Command) -> String {
+        let function_prefix = format!(
+            "__intellicomp_{}",
+            command_name.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+        );
+
+        let mut value_options = vec![];
+        let mut transitions = vec![];
+        collect_state(command, &[], &mut value_options, &mut transitions);
+
+        let mut script = vec![
+            format!("# Completions for {command_name}, generated by intellicomp"),
+            String::new(),
+            generate_state_function(&function_prefix, &value_options, &transitions),
+            generate_helper_functions(&function_prefix),
+            format!("complete -c {} -f", quote(command_name)),
+        ];
+        generate_scope_completions(&mut script, command_name, &function_prefix, command, &[]);
+
+        script.join("\n") + "\n"
+    }
+}
+
+/// Generate a function which prints the subcommand path of the command line followed by the
+/// number of positional arguments given to that subcommand before the cursor.
+fn generate_state_function(
+    function_prefix: &str,
+    value_options: &[String],
+    transitions: &[(String, String)],
+) -> String {
+    let mut lines = vec![
+        format!("function {function_prefix}_state"),
+        "    set -l tokens (commandline -opc)".to_string(),
+        "    set -e tokens[1]".to_string(),
+        "    set -l scope ''".to_string(),
+        "    set -l position 0".to_string(),
+        "    set -l skip_value 0".to_string(),
+        "    for token in $tokens".to_string(),
+        "        if test $skip_value -eq 1".to_string(),
+        "            set skip_value 0".to_string(),
+        "            continue".to_string(),
+        "        end".to_string(),
+        "        switch $token".to_string(),
+    ];
+
+    if !value_options.is_empty() {
+        lines.push(format!(
+            "            case {}",
+            value_options
+                .iter()
+                .map(|option| quote(option))
+                .collect::<Vec<_>>()
+                .join(" ")
+        ));
+        lines.push("                set skip_value 1".to_string());
+    }
+    lines.push("            case '-*'".to_string());
+    lines.push("            case '*'".to_string());
+
+    if !transitions.is_empty() {
+        lines.push("                if test $position -eq 0".to_string());
+        lines.push("                    switch \"$scope:$token\"".to_string());
+        for (transition, scope) in transitions {
+            lines.push(format!(
+                "                        case {}",
+                quote(transition)
+            ));
+            lines.push(format!(
+                "                            set scope {}",
+                quote(scope)
+            ));
+            lines.push("                            continue".to_string());
+        }
+        lines.push("                    end".to_string());
+        lines.push("                end".to_string());
+    }
+
+    lines.extend([
+        "                set position (math $position + 1)".to_string(),
+        "        end".to_string(),
+        "    end".to_string(),
+        "    echo $scope".to_string(),
+        "    echo $position".to_string(),
+        "end".to_string(),
+        String::new(),
+    ]);
+
+    lines.join("\n")
+}
+
+fn generate_helper_functions(function_prefix: &str) -> String {
+    format!(
+        r#"# Succeeds if the command line is within the subcommand path given as arguments
+function {function_prefix}_in
+    set -l state ({function_prefix}_state)
+    test "$state[1]" = "$argv"
+end
+
+# Like __fish_is_nth_token, succeeds if the word being completed is positional argument
+# number $argv[1] of the subcommand path given as the remaining arguments. Unlike it, the values
+# of options and the subcommands leading to the path aren't counted.
+function {function_prefix}_is_nth_token
+    set -l state ({function_prefix}_state)
+    set -l n $argv[1]
+    set -e argv[1]
+    not string match -q -- '-*' (commandline -ct)
+    and test (math $state[2] + 1) -eq $n
+    and test "$state[1]" = "$argv"
+end
+
+# Succeeds if any of the given options appear before the cursor
+function {function_prefix}_seen
+    set -l tokens (commandline -opc)
+    for option in $argv
+        contains -- $option $tokens
+        and return 0
+    end
+    return 1
+end
+"#
+    )
+}
+
+fn generate_scope_completions(
+    script: &mut Vec<String>,
+    command_name: &str,
+    function_prefix: &str,
+    command: &Command,
+    path: &[&str],
+) {
+    let base_command = format!("complete -c {}", quote(command_name));
+    let scope = path.iter().map(|word| quote(word)).collect::<Vec<_>>();
+    let in_scope = [format!("{function_prefix}_in")]
+        .into_iter()
+        .chain(scope.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let is_nth_token = |n: usize| {
+        [format!("{function_prefix}_is_nth_token"), n.to_string()]
+            .into_iter()
+            .chain(scope.iter().cloned())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    for argument in &command.keyword_arguments {
+        let mut conditions = vec![in_scope.clone()];
+
+        let excluded_spellings = get_excluding_spellings(argument, command);
+        if !excluded_spellings.is_empty() {
+            conditions.push(format!(
+                "not {function_prefix}_seen {}",
+                excluded_spellings
+                    .iter()
+                    .map(|spelling| quote(spelling))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ));
+        }
+
+        let mut completion = format!(
+            "{base_command} -n {} {} {}",
+            quote(&conditions.join("; and ")),
+            match argument.style {
+                KeywordArgumentStyle::Standard => "-l",
+                KeywordArgumentStyle::Old => "-o",
+            },
+            quote(&argument.name)
+        );
+
+        if let Some(shorthand) = argument.shorthand {
+            completion = format!("{completion} -s {}", quote(&shorthand.to_string()));
+        }
+
+        completion = format!("{completion} -d {}", quote(&argument.description));
+
+        if let Some(values) = get_value_completions(&argument.value_type, Some("-x"), "-r -F") {
+            completion = format!("{completion} {values}");
+        }
+
+        script.push(completion);
+    }
+
+    for (position, argument) in command.positional_arguments.iter().enumerate() {
+        if let Some(values) = get_value_completions(&argument.value_type, None, "-F") {
+            script.push(format!(
+                "{base_command} -n {} -d {} {values}",
+                quote(&is_nth_token(position + 1)),
+                quote(&argument.description),
+            ));
+        }
+    }
+
+    for subcommand in &command.subcommands {
+        script.push(format!(
+            "{base_command} -n {} -a {} -d {}",
+            quote(&is_nth_token(1)),
+            quote(&quote(&subcommand.name)),
+            quote(&subcommand.command.description)
+        ));
+    }
+
+    for subcommand in &command.subcommands {
+        let mut subcommand_path = path.to_vec();
+        subcommand_path.push(&subcommand.name);
+
+        generate_scope_completions(
+            script,
+            command_name,
+            function_prefix,
+            &subcommand.command,
+            &subcommand_path,
+        );
+    }
+}
+
+/// Get the `complete` options describing the value of an argument, given the options to use
+/// for values that can't be completed and for paths. Returns `None` if there is nothing to
+/// complete for the value.
+fn get_value_completions(
+    value_type: &ValueType,
+    exclusive_options: Option<&str>,
+    path_options: &str,
+) -> Option<String> {
+    match value_type {
+        ValueType::Flag => None,
+        ValueType::String => exclusive_options.map(str::to_string),
+        ValueType::Path => Some(path_options.to_string()),
+        ValueType::Enumeration(values) => {
+            let values = quote(
+                &values
+                    .iter()
+                    .map(|value| quote(value))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            );
+
+            Some(match exclusive_options {
+                Some(options) => format!("{options} -a {values}"),
+                None => format!("-a {values}"),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Quote a string for use as a single fish word, leaving it bare where that is unambiguous.
+fn quote(string: &str) -> String {
+    if !string.is_empty()
+        && string
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=+,@%".contains(c))
+    {
+        string.to_string()
+    } else {
+        format!("'{}'", string.replace('\\', "\\\\").replace('\'', "\\'"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::quote;
+    use crate::{export::tests::load_test_schema, ExportableShell, Fish};
+
+    #[test]
+    fn test_export_matches_golden_script() {
+        let schema = load_test_schema();
+        assert_eq!(
+            Fish.export_completions("vcs", schema.command()),
+            include_str!("testdata/vcs.fish")
+        );
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("--config-file"), "--config-file");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("remote add"), "'remote add'");
+        assert_eq!(quote(r"it's a \ path"), r"'it\'s a \\ path'");
+    }
+}
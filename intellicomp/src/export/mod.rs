@@ -0,0 +1,146 @@
+use intellicomp_core::{Command, KeywordArgument, ValueType};
+
+mod bash;
+mod fish;
+
+pub trait ExportableShell {
+    /// Translate a command into a completion script that can be installed without intellicomp
+    /// being present at completion time.
+    fn export_completions(&self, command_name: &str, command: &Command) -> String;
+}
+
+/// Gather the option spellings that consume the following word as their value, and the words
+/// which move from one subcommand path to another.
+fn collect_state(
+    command: &Command,
+    path: &[&str],
+    value_options: &mut Vec<String>,
+    transitions: &mut Vec<(String, String)>,
+) {
+    for argument in &command.keyword_arguments {
+        if argument.value_type != ValueType::Flag {
+            for spelling in get_spellings(argument) {
+                if !value_options.contains(&spelling) {
+                    value_options.push(spelling);
+                }
+            }
+        }
+    }
+
+    for subcommand in &command.subcommands {
+        let mut subcommand_path = path.to_vec();
+        subcommand_path.push(&subcommand.name);
+
+        transitions.push((
+            format!("{}:{}", path.join(" "), subcommand.name),
+            subcommand_path.join(" "),
+        ));
+        collect_state(
+            &subcommand.command,
+            &subcommand_path,
+            value_options,
+            transitions,
+        );
+    }
+}
+
+fn get_spellings(argument: &KeywordArgument) -> Vec<String> {
+    let mut spellings = vec![argument.to_string()];
+    if let Some(shorthand) = argument.shorthand {
+        spellings.push(format!("-{shorthand}"));
+    }
+    spellings
+}
+
+/// Get the spellings of every argument which, once given, prevents the argument from being given
+/// again. This covers the argument itself if it isn't repeatable along with anything it is
+/// incompatible with.
+fn get_excluding_spellings(argument: &KeywordArgument, command: &Command) -> Vec<String> {
+    let mut spellings = vec![];
+    if !argument.repeatable {
+        spellings.extend(get_spellings(argument));
+    }
+    for other in &command.keyword_arguments {
+        if argument.incompatible_with.contains(&other.name)
+            || other.incompatible_with.contains(&argument.name)
+        {
+            spellings.extend(get_spellings(other));
+        }
+    }
+    spellings
+}
+
+/// Get a command and each of its nested subcommands, along with the subcommand path leading to
+/// each of them.
+fn get_scopes<'a>(command: &'a Command, path: Vec<&'a str>) -> Vec<(Vec<&'a str>, &'a Command)> {
+    let mut scopes = vec![];
+    for subcommand in &command.subcommands {
+        let mut subcommand_path = path.clone();
+        subcommand_path.push(&subcommand.name);
+        scopes.extend(get_scopes(&subcommand.command, subcommand_path));
+    }
+    scopes.insert(0, (path, command));
+    scopes
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::Path;
+
+    use intellicomp_core::CompiledCommand;
+
+    use super::{collect_state, get_excluding_spellings, get_scopes};
+    use crate::load_schema_file;
+
+    /// The schema the exported scripts are checked against, which has nested subcommands,
+    /// enumerations, paths, values which can't be completed and incompatible arguments.
+    pub(crate) fn load_test_schema() -> CompiledCommand {
+        let schema_file =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("src/export/testdata/vcs.yaml");
+        load_schema_file(&schema_file, false).unwrap()
+    }
+
+    #[test]
+    fn test_collect_state() {
+        let schema = load_test_schema();
+        let mut value_options = vec![];
+        let mut transitions = vec![];
+        collect_state(schema.command(), &[], &mut value_options, &mut transitions);
+
+        assert_eq!(
+            value_options,
+            vec!["--config-file", "--message", "-m", "--color", "-protocol"]
+        );
+        assert_eq!(
+            transitions,
+            vec![
+                (":remote".to_string(), "remote".to_string()),
+                ("remote:add".to_string(), "remote add".to_string()),
+                (":apply".to_string(), "apply".to_string()),
+            ]
+        );
+
+        let scopes = get_scopes(schema.command(), vec![])
+            .into_iter()
+            .map(|(path, _)| path.join(" "))
+            .collect::<Vec<_>>();
+        assert_eq!(scopes, vec!["", "remote", "remote add", "apply"]);
+    }
+
+    #[test]
+    fn test_incompatibilities_exclude_both_ways() {
+        let schema = load_test_schema();
+        let arguments = &schema.command().keyword_arguments;
+        let (verbose, quiet) = (&arguments[0], &arguments[1]);
+
+        // Repeatable, so only ruled out by the argument which declares it incompatible
+        assert_eq!(
+            get_excluding_spellings(verbose, schema.command()),
+            vec!["--quiet", "-q"]
+        );
+        assert_eq!(
+            get_excluding_spellings(quiet, schema.command()),
+            vec!["--quiet", "-q", "--verbose", "-v"]
+        );
+    }
+}
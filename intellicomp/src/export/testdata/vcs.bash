@@ -0,0 +1,107 @@
+# Completions for vcs, generated by intellicomp
+
+# Succeeds if none of the given options appear before the cursor
+_intellicomp_vcs_unused() {
+    local option word
+    for option in "$@"; do
+        for word in "${used[@]}"; do
+            [[ "$word" == "$option" ]] && return 1
+        done
+    done
+    return 0
+}
+
+# Offers each of the given candidates which start with the word being completed
+_intellicomp_vcs_filter() {
+    local candidate
+    for candidate in "$@"; do
+        [[ "$candidate" == "$cur"* ]] && COMPREPLY+=("$candidate")
+    done
+}
+
+# Offers the paths which start with the word being completed
+_intellicomp_vcs_paths() {
+    local path
+    compopt -o filenames 2>/dev/null
+    while IFS= read -r path; do
+        COMPREPLY+=("$path")
+    done < <(compgen -f -- "$cur")
+}
+
+_intellicomp_vcs() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    local scope='' position=0 value_option='' used=() word i
+    COMPREPLY=()
+
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        word="${COMP_WORDS[i]}"
+        if [[ -n "$value_option" ]]; then
+            value_option=''
+            continue
+        fi
+        case "$word" in
+            --config-file|--message|-m|--color|-protocol)
+                value_option="$word"
+                used+=("$word")
+                ;;
+            -*)
+                used+=("$word")
+                ;;
+            *)
+                if ((position == 0)); then
+                    case "$scope:$word" in
+                        :remote) scope=remote; continue ;;
+                        remote:add) scope='remote add'; continue ;;
+                        :apply) scope=apply; continue ;;
+                    esac
+                fi
+                position=$((position + 1))
+                ;;
+        esac
+    done
+
+    if [[ -n "$value_option" ]]; then
+        case "$scope:$value_option" in
+            :--config-file)
+                _intellicomp_vcs_paths
+                ;;
+            :--color)
+                _intellicomp_vcs_filter always auto never
+                ;;
+            remote:-protocol)
+                _intellicomp_vcs_filter ssh https
+                ;;
+        esac
+        return 0
+    fi
+
+    case "$scope" in
+        '')
+            _intellicomp_vcs_unused --quiet -q && _intellicomp_vcs_filter --verbose
+            _intellicomp_vcs_unused --quiet -q --verbose -v && _intellicomp_vcs_filter --quiet
+            _intellicomp_vcs_unused --config-file && _intellicomp_vcs_filter --config-file
+            _intellicomp_vcs_unused --message -m && _intellicomp_vcs_filter --message
+            _intellicomp_vcs_unused --color && _intellicomp_vcs_filter --color
+            ;;
+        remote)
+            _intellicomp_vcs_unused -protocol && _intellicomp_vcs_filter -protocol
+            ;;
+    esac
+
+    case "$scope:$position" in
+        :0)
+            _intellicomp_vcs_filter remote apply
+            ;;
+        remote:0)
+            _intellicomp_vcs_filter add
+            ;;
+        'remote add:1')
+            _intellicomp_vcs_filter 'origin'\''s' file://
+            ;;
+        apply:0)
+            _intellicomp_vcs_paths
+            ;;
+    esac
+}
+
+complete -F _intellicomp_vcs vcs
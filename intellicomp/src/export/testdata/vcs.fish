@@ -0,0 +1,78 @@
+# Completions for vcs, generated by intellicomp
+
+function __intellicomp_vcs_state
+    set -l tokens (commandline -opc)
+    set -e tokens[1]
+    set -l scope ''
+    set -l position 0
+    set -l skip_value 0
+    for token in $tokens
+        if test $skip_value -eq 1
+            set skip_value 0
+            continue
+        end
+        switch $token
+            case --config-file --message -m --color -protocol
+                set skip_value 1
+            case '-*'
+            case '*'
+                if test $position -eq 0
+                    switch "$scope:$token"
+                        case ':remote'
+                            set scope remote
+                            continue
+                        case 'remote:add'
+                            set scope 'remote add'
+                            continue
+                        case ':apply'
+                            set scope apply
+                            continue
+                    end
+                end
+                set position (math $position + 1)
+        end
+    end
+    echo $scope
+    echo $position
+end
+
+# Succeeds if the command line is within the subcommand path given as arguments
+function __intellicomp_vcs_in
+    set -l state (__intellicomp_vcs_state)
+    test "$state[1]" = "$argv"
+end
+
+# Like __fish_is_nth_token, succeeds if the word being completed is positional argument
+# number $argv[1] of the subcommand path given as the remaining arguments. Unlike it, the values
+# of options and the subcommands leading to the path aren't counted.
+function __intellicomp_vcs_is_nth_token
+    set -l state (__intellicomp_vcs_state)
+    set -l n $argv[1]
+    set -e argv[1]
+    not string match -q -- '-*' (commandline -ct)
+    and test (math $state[2] + 1) -eq $n
+    and test "$state[1]" = "$argv"
+end
+
+# Succeeds if any of the given options appear before the cursor
+function __intellicomp_vcs_seen
+    set -l tokens (commandline -opc)
+    for option in $argv
+        contains -- $option $tokens
+        and return 0
+    end
+    return 1
+end
+
+complete -c vcs -f
+complete -c vcs -n '__intellicomp_vcs_in; and not __intellicomp_vcs_seen --quiet -q' -l verbose -s v -d 'Print more output'
+complete -c vcs -n '__intellicomp_vcs_in; and not __intellicomp_vcs_seen --quiet -q --verbose -v' -l quiet -s q -d 'Print less output'
+complete -c vcs -n '__intellicomp_vcs_in; and not __intellicomp_vcs_seen --config-file' -l config-file -d 'Read the config from a file' -r -F
+complete -c vcs -n '__intellicomp_vcs_in; and not __intellicomp_vcs_seen --message -m' -l message -s m -d 'The message to use' -x
+complete -c vcs -n '__intellicomp_vcs_in; and not __intellicomp_vcs_seen --color' -l color -d 'When to colour the output' -x -a 'always auto never'
+complete -c vcs -n '__intellicomp_vcs_is_nth_token 1' -a remote -d 'Manage remotes'
+complete -c vcs -n '__intellicomp_vcs_is_nth_token 1' -a apply -d 'Apply a patch'
+complete -c vcs -n '__intellicomp_vcs_in remote; and not __intellicomp_vcs_seen -protocol' -o protocol -d 'The protocol to use' -x -a 'ssh https'
+complete -c vcs -n '__intellicomp_vcs_is_nth_token 1 remote' -a add -d 'Add a remote'
+complete -c vcs -n '__intellicomp_vcs_is_nth_token 2 remote add' -d 'The remote\'s URL' -a '\'origin\\\'s\' \'file://\''
+complete -c vcs -n '__intellicomp_vcs_is_nth_token 1 apply' -d 'The patch to apply' -F
@@ -1,11 +1,57 @@
 use std::path::Path;
 
-use crate::{CompletableShell, IntellicompError};
+use intellicomp_core::Completion;
+
+use super::get_completion_options;
+use crate::{
+    CompletableShell, CompletionRequest, ExportableShell, IntellicompError, SchemaDirectoryKind,
+    SchemaSearchPath,
+};
 
 pub struct Bash;
 
 impl CompletableShell for Bash {
+    fn name(&self) -> &'static str {
+        "bash"
+    }
+
+    /// Rather than registering every schema up front, a default completion is registered which
+    /// looks up the schema for a command the first time it is completed. This keeps shell
+    /// startup fast and picks up schemas added after the shell started.
+    fn generate_completion_commands(&self) -> Result<Vec<String>, IntellicompError> {
+        let search_path = SchemaSearchPath::load()?;
+        let executable = std::env::current_exe()?;
+
+        // The project directory and `INTELLICOMP_SCHEMA_PATH` are read each time instead
+        let schema_directories = search_path
+            .loaded_directories()
+            .filter(|directory| {
+                !matches!(
+                    directory.kind,
+                    SchemaDirectoryKind::Project | SchemaDirectoryKind::Environment
+                )
+            })
+            .map(|directory| {
+                directory
+                    .path
+                    .to_str()
+                    .map(quote)
+                    .ok_or(IntellicompError::InvalidUnicodeInPath)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(vec![get_loader(
+            &schema_directories,
+            &quote(
+                executable
+                    .to_str()
+                    .ok_or(IntellicompError::InvalidUnicodeInPath)?,
+            ),
+        )])
+    }
+
     fn generate_completions_from_schema(
+        &self,
         schema_file: &Path,
     ) -> Result<Vec<String>, IntellicompError> {
         let command_name = schema_file
@@ -15,12 +61,361 @@ impl CompletableShell for Bash {
             .unwrap()
             .trim_end_matches(".yaml");
 
+        // Relies on `_intellicomp_complete` from `generate_completion_commands`, which finds the
+        // schema from the command name.
         Ok(vec![format!(
-            "complete -C \"{} complete bash {}\" {command_name}",
-            std::env::current_exe()?
-                .to_str()
-                .ok_or(IntellicompError::InvalidUnicodeInPath)?,
-            schema_file.to_string_lossy()
+            "complete -F _intellicomp_complete {}",
+            quote(command_name)
         )])
     }
+
+    fn parse_request(&self, _extra: &[String]) -> Result<CompletionRequest, IntellicompError> {
+        let cursor_position = std::env::var("COMP_POINT")
+            .ok()
+            .and_then(|cursor_position| cursor_position.parse().ok())
+            .ok_or(IntellicompError::InvalidCompletionRequest(
+                "COMP_POINT is missing or invalid",
+            ))?;
+
+        let command = std::env::var("COMP_LINE")
+            .map_err(|_| IntellicompError::InvalidCompletionRequest("COMP_LINE is missing"))?;
+
+        Ok(CompletionRequest::Line {
+            command,
+            cursor_position,
+        })
+    }
+
+    fn render_candidates(&self, candidates: &[Completion]) -> String {
+        // Bash can't display descriptions, but when it is only listing the alternatives
+        // (COMP_TYPE of '?' or '!') the candidates are never inserted, so the descriptions can be
+        // included in them.
+        let is_listing = matches!(std::env::var("COMP_TYPE").as_deref(), Ok("63" | "33"));
+        render_candidates(candidates, is_listing)
+    }
+
+    /// Having no candidates, only ask for the default completion in the options line.
+    fn render_fallback(&self) -> String {
+        "default".to_string()
+    }
+
+    fn exporter(&self) -> Option<&dyn ExportableShell> {
+        Some(self)
+    }
+}
+
+/// The functions which find the schema for a command and complete it, given the quoted schema
+/// directories to look in after the project and the quoted path to intellicomp.
+///
+/// Bash splits `--name=value` into separate words, so the `--name=` which intellicomp puts
+/// before each candidate for the value is removed again.
+///
+/// The schema files are looked for by the shell so that schemas added later are found, and the
+/// project and `INTELLICOMP_SCHEMA_PATH` are read as the command is completed, as intellicomp
+/// does. The schemas of the current project are checked first, but as intellicomp only loads
+/// them once the project is trusted, a command is only taken over for a project schema if
+/// intellicomp can find a schema for it. Otherwise the completion is left to bash-completion's
+/// loader.
+fn get_loader(schema_directories: &[String], executable: &str) -> String {
+    format!(
+        r#"_intellicomp_find_schema() {{
+    local directory=$PWD
+    while [[ -n "$directory" && ! -d "$directory/.intellicomp" ]]; do
+        directory=${{directory%/*}}
+    done
+    if [[ -f "$directory/.intellicomp/${{1##*/}}.yaml" ]]; then
+        schema="$directory/.intellicomp/${{1##*/}}.yaml"
+        return 0
+    fi
+    local -a environment_directories
+    IFS=: read -ra environment_directories <<< "$INTELLICOMP_SCHEMA_PATH"
+    for directory in "${{environment_directories[@]}}" {}; do
+        if [[ -n "$directory" && -f "$directory/${{1##*/}}.yaml" ]]; then
+            schema="$directory/${{1##*/}}.yaml"
+            return 0
+        fi
+    done
+    return 1
+}}
+_intellicomp_complete() {{
+    local schema options option candidate prefix
+    _intellicomp_find_schema "$1" || return
+    COMPREPLY=()
+    {{
+        IFS= read -r options
+        for option in $options; do
+            compopt -o "$option"
+        done
+        while IFS= read -r candidate || [[ -n "$candidate" ]]; do
+            COMPREPLY+=("$candidate")
+        done
+    }} < <(COMP_LINE="$COMP_LINE" COMP_POINT="$COMP_POINT" COMP_TYPE="$COMP_TYPE" {executable} complete bash "$schema")
+    prefix=${{COMP_LINE:0:COMP_POINT}}
+    prefix=${{prefix##*[[:space:]]}}
+    if [[ $prefix == *=* && $COMP_WORDBREAKS == *=* ]]; then
+        prefix=${{prefix%"${{prefix##*=}}"}}
+        COMPREPLY=("${{COMPREPLY[@]#"$prefix"}}")
+    fi
+}}
+_intellicomp_load() {{
+    local schema
+    if _intellicomp_find_schema "$1" &&
+        {{ [[ $schema != */.intellicomp/* ]] || {executable} which -- "${{1##*/}}" >/dev/null 2>&1; }}; then
+        complete -F _intellicomp_complete "$1"
+        return 124
+    fi
+    declare -F _completion_loader >/dev/null && _completion_loader "$@"
+}}
+complete -D -o bashdefault -o default -F _intellicomp_load"#,
+        schema_directories.join(" "),
+    )
+}
+
+/// Render the candidates after a line holding the options for `compopt`, which the completion
+/// function applies before reading the candidates. A single candidate would be inserted even when
+/// bash is only listing the alternatives, so it is left without its description.
+fn render_candidates(candidates: &[Completion], is_listing: bool) -> String {
+    let rendered = if is_listing && candidates.len() > 1 {
+        render_candidates_with_descriptions(candidates)
+    } else {
+        candidates
+            .iter()
+            .map(|candidate| candidate.value.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    format!(
+        "{}\n{rendered}",
+        get_completion_options(candidates).join(" ")
+    )
+}
+
+/// Render each candidate followed by its description, with the descriptions aligned into a
+/// column.
+fn render_candidates_with_descriptions(candidates: &[Completion]) -> String {
+    let width = candidates
+        .iter()
+        .map(|candidate| candidate.value.chars().count())
+        .max()
+        .unwrap_or_default();
+
+    candidates
+        .iter()
+        .map(|candidate| match &candidate.description {
+            Some(description) => format!("{:width$}   {description}", candidate.value),
+            None => candidate.value.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Quote a string as a single bash word.
+fn quote(string: &str) -> String {
+    format!("'{}'", string.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use intellicomp_core::Completion;
+
+    use super::{get_loader, quote, render_candidates};
+
+    fn candidate(value: &str, description: Option<&str>) -> Completion {
+        Completion {
+            value: value.to_string(),
+            description: description.map(String::from),
+            no_space: false,
+            is_filename: false,
+        }
+    }
+
+    #[test]
+    fn test_descriptions_are_shown_when_listing() {
+        let candidates = [
+            candidate("--verbose", Some("Print more output")),
+            candidate("--color", Some("When to colour the output")),
+            candidate("remote", None),
+        ];
+
+        assert_eq!(
+            render_candidates(&candidates, true),
+            "\n--verbose   Print more output\n\
+             --color     When to colour the output\n\
+             remote"
+        );
+        // A single candidate is inserted even when listing
+        assert_eq!(render_candidates(&candidates[..1], true), "\n--verbose");
+    }
+
+    #[test]
+    fn test_descriptions_are_left_out_when_inserting() {
+        let mut candidates = [
+            candidate("--verbose", Some("Print more output")),
+            candidate("src/", None),
+        ];
+        candidates[1].no_space = true;
+        candidates[1].is_filename = true;
+
+        // A space still follows `--verbose`, so it follows the directory too
+        assert_eq!(
+            render_candidates(&candidates, false),
+            "filenames\n--verbose\nsrc/"
+        );
+        assert_eq!(
+            render_candidates(&candidates[1..], false),
+            "nospace filenames\nsrc/"
+        );
+        assert_eq!(render_candidates(&[], false), "\n");
+    }
+
+    #[test]
+    fn test_loader_registers_and_completes_commands_with_schemas() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-bash-complete-{}", std::process::id()));
+        let schemas = directory.join("schemas");
+        std::fs::create_dir_all(&schemas).unwrap();
+        std::fs::write(schemas.join("tool.yaml"), "").unwrap();
+
+        // Stands in for intellicomp, giving no options and then what it was given as candidates
+        let executable = directory.join("intellicomp");
+        std::fs::write(
+            &executable,
+            "#!/bin/sh\nprintf '\\n%s\\n%s %s\\n' \"$3\" \"$COMP_LINE\" \"$COMP_POINT\"\n",
+        )
+        .unwrap();
+        let script = format!(
+            r#"{}
+complete -p -D
+_intellicomp_load tool; echo "load: $?"
+complete -p tool
+COMP_LINE='tool --ver' COMP_POINT=10
+_intellicomp_complete tool 2>/dev/null
+printf '%s\n' "${{COMPREPLY[@]}}""#,
+            get_loader(
+                &[quote(schemas.to_str().unwrap())],
+                &format!("sh {}", quote(executable.to_str().unwrap()))
+            ),
+        );
+
+        if let Ok(output) = Command::new("bash").arg("-c").arg(script).output() {
+            assert_eq!(
+                String::from_utf8(output.stdout).unwrap(),
+                format!(
+                    "complete -o bashdefault -o default -F _intellicomp_load -D\n\
+                     load: 124\n\
+                     complete -F _intellicomp_complete tool\n\
+                     {}\n\
+                     tool --ver 10\n",
+                    schemas.join("tool.yaml").display()
+                )
+            );
+        }
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_completion_removes_the_name_before_an_equals_sign() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-bash-equals-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("tool.yaml"), "").unwrap();
+
+        let executable = directory.join("intellicomp");
+        std::fs::write(
+            &executable,
+            "#!/bin/sh\nprintf 'nospace\\n--format=json\\n--format=yaml\\n'\n",
+        )
+        .unwrap();
+        let complete = |word_breaks: &str| {
+            let script = format!(
+                "{}\nCOMP_WORDBREAKS={}\nCOMP_LINE='tool --format=j' COMP_POINT=15\n\
+                 _intellicomp_complete tool 2>/dev/null\nprintf '%s\\n' \"${{COMPREPLY[@]}}\"",
+                get_loader(
+                    &[quote(directory.to_str().unwrap())],
+                    &format!("sh {}", quote(executable.to_str().unwrap()))
+                ),
+                quote(word_breaks),
+            );
+            let output = Command::new("bash").arg("-c").arg(script).output().ok()?;
+            Some(String::from_utf8(output.stdout).unwrap())
+        };
+
+        if let Some(output) = complete(" =") {
+            assert_eq!(output, "json\nyaml\n");
+            assert_eq!(complete(" ").unwrap(), "--format=json\n--format=yaml\n");
+        }
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_loader_reads_the_schema_path_when_completing() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-bash-path-{}", std::process::id()));
+        let schemas = directory.join("with space");
+        std::fs::create_dir_all(&schemas).unwrap();
+        std::fs::write(schemas.join("tool.yaml"), "").unwrap();
+
+        // The variable is set after the loader is defined, as if changed in a running shell
+        let load = |schema_path: &str| {
+            let script = format!(
+                "{}\nINTELLICOMP_SCHEMA_PATH={}\n_intellicomp_load tool\necho $?",
+                get_loader(&[], "false"),
+                quote(schema_path),
+            );
+            let output = Command::new("bash").arg("-c").arg(script).output().ok()?;
+            Some(String::from_utf8(output.stdout).unwrap().trim().to_string())
+        };
+
+        if let Some(output) = load("") {
+            assert_eq!(output, "1");
+            assert_eq!(
+                load(&format!("/nonexistent::{}", schemas.display())).unwrap(),
+                "124"
+            );
+        }
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_loader_only_takes_over_project_schemas_intellicomp_finds() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-bash-loader-{}", std::process::id()));
+        let project = directory.join("project");
+        let user = directory.join("user");
+        for path in [
+            project.join(".intellicomp/tool.yaml"),
+            project.join("src/.keep"),
+            user.join("other.yaml"),
+        ] {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let schema_directories = [quote(user.to_str().unwrap())];
+
+        // `true` and `false` stand in for intellicomp, finding a schema or not
+        let load = |executable: &str, command_name: &str| {
+            let script = format!(
+                "{}\ncd {}\n_intellicomp_load {command_name}\necho $?",
+                get_loader(&schema_directories, executable),
+                quote(project.join("src").to_str().unwrap()),
+            );
+            let output = Command::new("bash").arg("-c").arg(script).output().ok()?;
+            Some(String::from_utf8(output.stdout).unwrap().trim().to_string())
+        };
+
+        if let Some(status) = load("true", "tool") {
+            assert_eq!(status, "124");
+            assert_eq!(load("false", "tool").unwrap(), "1");
+            assert_eq!(load("false", "other").unwrap(), "124");
+            assert_eq!(load("true", "missing").unwrap(), "1");
+        }
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
 }
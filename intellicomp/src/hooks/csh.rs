@@ -0,0 +1,149 @@
+use std::{fs, path::Path};
+
+use intellicomp_core::Completion;
+use itertools::Itertools;
+
+use crate::{CompletableShell, CompletionRequest, IntellicompError};
+
+pub struct Csh;
+
+impl CompletableShell for Csh {
+    fn name(&self) -> &'static str {
+        "csh"
+    }
+
+    fn generate_completions_from_schema(
+        &self,
+        schema_file: &Path,
+    ) -> Result<Vec<String>, IntellicompError> {
+        let command_name = schema_file
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .trim_end_matches(".yaml");
+
+        // tcsh exposes the line being completed through the COMMAND_LINE environment variable
+        // while running the backquoted command.
+        Ok(vec![format!(
+            "complete {command_name} 'p/*/`\"{}\" complete csh \"{}\"`/'",
+            std::env::current_exe()?
+                .to_str()
+                .ok_or(IntellicompError::InvalidUnicodeInPath)?,
+            schema_file.to_string_lossy()
+        )])
+    }
+
+    fn parse_request(&self, _extra: &[String]) -> Result<CompletionRequest, IntellicompError> {
+        // tcsh only provides the line up to the cursor
+        let command = std::env::var("COMMAND_LINE")
+            .map_err(|_| IntellicompError::InvalidCompletionRequest("COMMAND_LINE is missing"))?;
+
+        Ok(CompletionRequest::Line {
+            cursor_position: command.len(),
+            command,
+        })
+    }
+
+    /// tcsh splits the output of the backquoted command into words at whitespace, without
+    /// honouring quotes or escapes, and quotes the word it inserts itself. So candidates are
+    /// given as they are, apart from those containing whitespace, which can't be given at all.
+    fn render_candidates(&self, candidates: &[Completion]) -> String {
+        candidates
+            .iter()
+            .map(|candidate| candidate.value.as_str())
+            .filter(|value| !value.is_empty() && !value.contains(char::is_whitespace))
+            .join(" ")
+    }
+
+    /// tcsh only offers the words the completion command prints, so rather than falling back
+    /// to the shell the paths matching the word being completed are listed.
+    fn render_fallback(&self) -> String {
+        let command = std::env::var("COMMAND_LINE").unwrap_or_default();
+        let word = match command.rsplit_once(char::is_whitespace) {
+            Some((_, word)) => word,
+            None => "",
+        };
+        self.render_candidates(&get_path_candidates(word))
+    }
+}
+
+/// The paths starting with a partially typed one, leaving out hidden files unless the partial
+/// file name starts with a dot.
+fn get_path_candidates(partial_path: &str) -> Vec<Completion> {
+    let (directory, prefix) = match partial_path.rsplit_once('/') {
+        Some((directory, prefix)) => (format!("{directory}/"), prefix),
+        None => (String::new(), partial_path),
+    };
+    let Ok(entries) = fs::read_dir(if directory.is_empty() {
+        "."
+    } else {
+        &directory
+    }) else {
+        return vec![];
+    };
+
+    let mut candidates = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| {
+            name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.'))
+        })
+        .map(|name| Completion::for_path(Path::new(&format!("{directory}{name}"))))
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| a.value.cmp(&b.value));
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use intellicomp_core::Completion;
+
+    use super::{get_path_candidates, Csh};
+    use crate::CompletableShell;
+
+    #[test]
+    fn test_render_candidates_verbatim() {
+        let candidates =
+            ["--verbose", "origin's", "a*b", "two words", "file\\name"].map(|value| Completion {
+                value: value.to_string(),
+                description: Some("Ignored".to_string()),
+                no_space: false,
+                is_filename: false,
+            });
+
+        assert_eq!(
+            Csh.render_candidates(&candidates),
+            "--verbose origin's a*b file\\name"
+        );
+    }
+
+    #[test]
+    fn test_fallback_lists_matching_paths() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-csh-{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("src")).unwrap();
+        for file_name in ["schema.yaml", "spec.yaml", ".hidden", "other"] {
+            std::fs::write(directory.join(file_name), "").unwrap();
+        }
+        let partial_path = format!("{}/s", directory.display());
+
+        assert_eq!(
+            get_path_candidates(&partial_path)
+                .into_iter()
+                .map(|candidate| candidate.value)
+                .collect::<Vec<_>>(),
+            vec![
+                format!("{}/schema.yaml", directory.display()),
+                format!("{}/spec.yaml", directory.display()),
+                format!("{}/src/", directory.display()),
+            ]
+        );
+        assert_eq!(
+            get_path_candidates(&format!("{}/.", directory.display())).len(),
+            1
+        );
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,124 @@
+use std::path::Path;
+
+use intellicomp_core::Completion;
+
+use crate::{CompletableShell, CompletionRequest, IntellicompError};
+
+pub struct Elvish;
+
+impl CompletableShell for Elvish {
+    fn name(&self) -> &'static str {
+        "elvish"
+    }
+
+    fn generate_completions_from_schema(
+        &self,
+        schema_file: &Path,
+    ) -> Result<Vec<String>, IntellicompError> {
+        let command_name = schema_file
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .trim_end_matches(".yaml");
+
+        // Candidates are emitted as a stream of JSON objects, one per line. Should intellicomp
+        // fail, filenames are completed instead.
+        Ok(vec![format!(
+            "set edit:completion:arg-completer[{}] = {{|@args| try {{ {} complete elvish {} $@args | from-json | each {{|c| edit:complex-candidate $c[value] &display=$c[display] &code-suffix=$c[suffix] }} }} catch {{ edit:complete-filename $args[-1] }} }}",
+            quote(command_name),
+            quote(
+                std::env::current_exe()?
+                    .to_str()
+                    .ok_or(IntellicompError::InvalidUnicodeInPath)?
+            ),
+            quote(&schema_file.to_string_lossy())
+        )])
+    }
+
+    fn parse_request(&self, extra: &[String]) -> Result<CompletionRequest, IntellicompError> {
+        // Elvish passes the words of the command line up to the cursor
+        Ok(CompletionRequest::Words(extra.to_vec()))
+    }
+
+    fn render_candidates(&self, candidates: &[Completion]) -> String {
+        candidates
+            .iter()
+            .map(|candidate| {
+                let display = match &candidate.description {
+                    Some(description) => format!("{}  {description}", candidate.value),
+                    None => candidate.value.clone(),
+                };
+
+                let suffix = if candidate.no_space { "" } else { " " };
+
+                format!(
+                    "{}\n",
+                    serde_json::json!({
+                        "value": candidate.value,
+                        "display": display,
+                        "suffix": suffix,
+                    })
+                )
+            })
+            .collect()
+    }
+}
+
+/// Quote a string as an Elvish single-quoted string literal.
+fn quote(string: &str) -> String {
+    format!("'{}'", string.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use intellicomp_core::Completion;
+
+    use super::{quote, Elvish};
+    use crate::{CompletableShell, CompletionRequest};
+
+    #[test]
+    fn test_parse_request_passes_words_through() {
+        let words = ["git", "commit", "--message", "two words"].map(String::from);
+
+        assert_eq!(
+            Elvish.parse_request(&words).unwrap(),
+            CompletionRequest::Words(words.to_vec())
+        );
+    }
+
+    #[test]
+    fn test_render_candidates_as_json_lines() {
+        let candidates = [
+            Completion {
+                value: "--verbose".to_string(),
+                description: Some("Print more output".to_string()),
+                no_space: false,
+                is_filename: false,
+            },
+            Completion {
+                value: "src/".to_string(),
+                description: None,
+                no_space: true,
+                is_filename: true,
+            },
+        ];
+
+        assert_eq!(
+            Elvish.render_candidates(&candidates),
+            concat!(
+                r#"{"display":"--verbose  Print more output","suffix":" ","value":"--verbose"}"#,
+                "\n",
+                r#"{"display":"src/","suffix":"","value":"src/"}"#,
+                "\n",
+            )
+        );
+        assert_eq!(Elvish.render_candidates(&[]), "");
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("git"), "'git'");
+        assert_eq!(quote("it's"), "'it''s'");
+    }
+}
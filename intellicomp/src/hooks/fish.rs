@@ -1,40 +1,178 @@
-use std::fs;
 use std::path::Path;
 
-use intellicomp_core::Command;
+use intellicomp_core::Completion;
 
-use crate::{CompletableShell, IntellicompError};
+use super::{get_byte_offset, get_completion_options};
+use crate::{
+    get_schema_files, CompletableShell, CompletionRequest, ExportableShell, IntellicompError,
+};
 
 pub struct Fish;
 
+/// Print the candidates for a command line, given the path to intellicomp and the schema. Paths
+/// are completed by fish if intellicomp asks for them or can't complete the line.
+const COMPLETION_FUNCTION: &str = r#"function __intellicomp_complete
+    set -l output ($argv[1] complete fish $argv[2] (commandline -cp | string collect) (commandline -pC))
+    or begin
+        __fish_complete_path (commandline -ct)
+        return
+    end
+    set -l options (string split ' ' -- $output[1])
+    set -e output[1]
+    string join \n -- $output
+    if contains filenames $options
+        __fish_complete_path (commandline -ct)
+    end
+end"#;
+
 impl CompletableShell for Fish {
+    fn name(&self) -> &'static str {
+        "fish"
+    }
+
+    /// Every schema is completed through one function, which hands paths over to fish's own
+    /// path completion so that they're escaped and described like any others.
+    fn generate_completion_commands(&self) -> Result<Vec<String>, IntellicompError> {
+        let mut completion_commands = vec![COMPLETION_FUNCTION.to_string()];
+
+        for schema_file in get_schema_files()? {
+            completion_commands.extend(self.generate_completions_from_schema(&schema_file)?);
+        }
+        Ok(completion_commands)
+    }
+
     fn generate_completions_from_schema(
+        &self,
         schema_file: &Path,
     ) -> Result<Vec<String>, IntellicompError> {
-        let schema: Command = serde_yaml::from_reader(fs::File::open(schema_file)?)?;
-        let file_name = schema_file.file_name().unwrap().to_string_lossy();
+        let command_name = schema_file
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .trim_end_matches(".yaml");
 
-        let command_name = file_name.strip_suffix(".yaml");
+        let executable = std::env::current_exe()?;
+        let executable = executable
+            .to_str()
+            .ok_or(IntellicompError::InvalidUnicodeInPath)?;
+        let schema_file = schema_file
+            .to_str()
+            .ok_or(IntellicompError::InvalidUnicodeInPath)?;
 
-        let mut completion_commands = vec![];
-
-        if let Some(command_name) = command_name {
-            let base_command = format!("complete -c {command_name}");
+        // File completions are disabled as paths are completed by intellicomp itself where the
+        // schema allows for them, so they are only offered if intellicomp asks for them or fails.
+        // The arguments are expanded when completing, so the paths are quoted a second time.
+        Ok(vec![format!(
+            "complete -c {} -f -a {}",
+            quote(command_name),
+            quote(&format!(
+                "(__intellicomp_complete {} {})",
+                quote(executable),
+                quote(schema_file)
+            ))
+        )])
+    }
 
-            for argument in schema.keyword_arguments {
-                let description = argument.description;
+    fn parse_request(&self, extra: &[String]) -> Result<CompletionRequest, IntellicompError> {
+        // Fish passes the current process up to the cursor, followed by the cursor position
+        // within that process.
+        let [command, cursor_position] = extra else {
+            return Err(IntellicompError::InvalidCompletionRequest(
+                "Expected the command line and cursor position from fish",
+            ));
+        };
+        let cursor_position = cursor_position.parse().map_err(|_| {
+            IntellicompError::InvalidCompletionRequest("The cursor position is not a number")
+        })?;
 
-                let mut command =
-                    format!("{base_command} -l '{}' -d \'{description}\'", argument.name);
+        Ok(CompletionRequest::Line {
+            cursor_position: get_byte_offset(command, cursor_position),
+            command: command.clone(),
+        })
+    }
 
-                if let Some(shorthand) = argument.shorthand {
-                    command = format!("{command} -s '{shorthand}'")
+    /// The first line holds the hints for the candidates, like for bash. Fish leaves out the
+    /// space after candidates ending in `/` or `=` by itself, so only `filenames` is acted on, by
+    /// completing paths with fish in place of the path candidates.
+    fn render_candidates(&self, candidates: &[Completion]) -> String {
+        let mut rendered = format!("{}\n", get_completion_options(candidates).join(" "));
+        for candidate in candidates.iter().filter(|candidate| !candidate.is_filename) {
+            match &candidate.description {
+                Some(description) => {
+                    rendered.push_str(&format!("{}\t{description}\n", candidate.value))
                 }
-
-                completion_commands.push(command);
+                None => rendered.push_str(&format!("{}\n", candidate.value)),
             }
         }
+        rendered
+    }
 
-        Ok(completion_commands)
+    fn exporter(&self) -> Option<&dyn ExportableShell> {
+        Some(self)
+    }
+}
+
+/// Quote a string as a fish single-quoted string, within which only `\` and `'` are escaped.
+fn quote(string: &str) -> String {
+    format!("'{}'", string.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use intellicomp_core::Completion;
+
+    use super::{quote, Fish};
+    use crate::{CompletableShell, CompletionRequest};
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(
+            Fish.parse_request(&["git ré".to_string(), "6".to_string()])
+                .unwrap(),
+            CompletionRequest::Line {
+                command: "git ré".to_string(),
+                cursor_position: 7
+            }
+        );
+        assert!(Fish.parse_request(&["git ".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_paths_are_left_to_fish() {
+        let candidates = [
+            Completion::with_description("--verbose", "Print more output"),
+            Completion::new("remote"),
+            Completion::for_path(Path::new("Cargo.toml")),
+        ];
+
+        assert_eq!(
+            Fish.render_candidates(&candidates),
+            "filenames\n--verbose\tPrint more output\nremote\n"
+        );
+        assert_eq!(Fish.render_candidates(&candidates[1..2]), "\nremote\n");
+    }
+
+    #[test]
+    fn test_paths_are_quoted() {
+        assert_eq!(quote(r"it's a \path"), r"'it\'s a \\path'");
+
+        let commands = Fish
+            .generate_completions_from_schema(Path::new("/schemas/it's here/tool.yaml"))
+            .unwrap();
+        let executable = std::env::current_exe().unwrap();
+        assert_eq!(
+            commands,
+            vec![format!(
+                "complete -c 'tool' -f -a {}",
+                quote(&format!(
+                    "(__intellicomp_complete {} '/schemas/it\\'s here/tool.yaml')",
+                    quote(executable.to_str().unwrap())
+                ))
+            )]
+        );
+        assert!(commands[0].ends_with(r"'/schemas/it\\\'s here/tool.yaml\')'"));
     }
 }
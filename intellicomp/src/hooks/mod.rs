@@ -9,49 +9,191 @@ pub use fish::Fish;
 mod bash;
 pub use bash::Bash;
 
-use directories::ProjectDirs;
-use git2::build::RepoBuilder;
+mod csh;
+pub use csh::Csh;
 
-use crate::IntellicompError;
+mod elvish;
+pub use elvish::Elvish;
 
+mod nu;
+pub use nu::Nu;
+
+mod xonsh;
+pub use xonsh::Xonsh;
+
+mod registry;
+pub use registry::ShellRegistry;
+
+use intellicomp_core::{Command, CommandParseError, CompiledCommand, Completion};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    extract_embedded_schemas, migrate_community_clone, ExportableShell, FetchOutcome,
+    IntellicompError, SchemaFilter, SchemaSearchPath, SchemaSource, SourceList, TrustStore,
+};
+
+/// A shell which intellicomp is able to provide completions for.
+///
+/// Shells are registered with a [`ShellRegistry`], allowing crates which depend on intellicomp
+/// to add support for further shells.
 pub trait CompletableShell {
-    fn generate_completion_commands() -> Result<Vec<String>, IntellicompError> {
+    /// The name used to refer to this shell on the command line, e.g. `bash`.
+    fn name(&self) -> &'static str;
+
+    /// Generate the hook which, once evaluated by the shell, registers completions for every
+    /// schema.
+    fn generate_completion_commands(&self) -> Result<Vec<String>, IntellicompError> {
         let mut completion_commands = vec![];
 
-        let schema_directory = get_or_update_schema_dir()?;
-
-        for schema_file in fs::read_dir(schema_directory)?
-            .filter_map(|f| f.ok())
-            .filter(|f| {
-                f.path()
-                    .extension()
-                    .map(|e| e.to_string_lossy())
-                    .unwrap_or("".into())
-                    == "yaml"
-            })
-        {
-            completion_commands
-                .extend(Self::generate_completions_from_schema(&schema_file.path())?.into_iter());
+        for schema_file in get_schema_files()? {
+            completion_commands.extend(self.generate_completions_from_schema(&schema_file)?);
         }
         Ok(completion_commands)
     }
 
     fn generate_completions_from_schema(
+        &self,
         schema_file: &Path,
     ) -> Result<Vec<String>, IntellicompError>;
+
+    /// Read the command line being completed from the extra arguments passed by the hook, or
+    /// from the environment for shells which expose it there.
+    fn parse_request(&self, extra: &[String]) -> Result<CompletionRequest, IntellicompError>;
+
+    /// Format the candidates in the way the hook expects to read them.
+    fn render_candidates(&self, candidates: &[Completion]) -> String;
+
+    /// The output which makes the hook fall back to the shell's own completion, used when the
+    /// command line can't be completed. The process also exits unsuccessfully, which hooks may
+    /// check instead.
+    fn render_fallback(&self) -> String {
+        String::new()
+    }
+
+    /// The exporter for standalone completion scripts, if this shell supports them.
+    fn exporter(&self) -> Option<&dyn ExportableShell> {
+        None
+    }
+}
+
+/// The command line being completed, as received from a shell.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompletionRequest {
+    /// An unsplit command line along with the byte offset of the cursor within it.
+    Line {
+        command: String,
+        cursor_position: usize,
+    },
+
+    /// A command line which the shell has already split into words, ending with the word being
+    /// completed.
+    Words(Vec<String>),
+}
+
+impl CompletionRequest {
+    /// Generate the candidates for the command line from a schema.
+    pub fn generate_candidates(
+        &self,
+        schema: &CompiledCommand,
+    ) -> Result<Vec<Completion>, CommandParseError> {
+        match self {
+            CompletionRequest::Line {
+                command,
+                cursor_position,
+            } => schema.generate_candidates(command, *cursor_position),
+            CompletionRequest::Words(words) => schema.generate_candidates_from_words(words),
+        }
+    }
+
+    /// Generate the candidates for the command line from a schema, completing relative paths
+    /// from the given directory rather than the working directory.
+    pub fn generate_candidates_in(
+        &self,
+        schema: &CompiledCommand,
+        working_directory: &Path,
+    ) -> Result<Vec<Completion>, CommandParseError> {
+        let state = match self {
+            CompletionRequest::Line {
+                command,
+                cursor_position,
+            } => schema.parse_state(command, *cursor_position)?,
+            CompletionRequest::Words(words) => schema.parse_state_from_words(words)?,
+        };
+        state.candidates_in(working_directory)
+    }
+}
+
+/// Get the hints needed by the candidates, named after bash's `compopt` options: `nospace` if
+/// none should be followed by a space, and `filenames` if any are paths, so that the shell can
+/// quote them and mark directories. Bash applies the options to every candidate, so a space is
+/// still added after the others when only some shouldn't be followed by one.
+pub(crate) fn get_completion_options(candidates: &[Completion]) -> Vec<&'static str> {
+    let mut options = vec![];
+    if !candidates.is_empty() && candidates.iter().all(|candidate| candidate.no_space) {
+        options.push("nospace");
+    }
+    if candidates.iter().any(|candidate| candidate.is_filename) {
+        options.push("filenames");
+    }
+    options
 }
 
-pub fn get_or_update_schema_dir() -> Result<PathBuf, IntellicompError> {
-    let dirs = ProjectDirs::from("com", "dob9601", "intellicomp").unwrap();
-    let schema_dir = dirs.data_dir();
-    if !schema_dir.exists() {
-        println!("echo 'Pulling schemas from dob9601/intellicomp-schemas'");
+/// Convert a cursor position measured in characters, as reported by most shells, into a byte
+/// offset.
+fn get_byte_offset(command: &str, cursor_position: usize) -> usize {
+    command
+        .char_indices()
+        .nth(cursor_position)
+        .map(|(index, _)| index)
+        .unwrap_or(command.len())
+}
+
+/// Read and parse a schema file.
+pub fn load_schema(schema_file: &Path) -> Result<Command, IntellicompError> {
+    Ok(serde_yaml::from_reader(fs::File::open(schema_file)?)?)
+}
+
+/// The schema used for each command, out of every directory in the search path. Schemas which
+/// are held back by their source's trust level or don't match their source's signed manifest are
+/// left out.
+pub fn get_schema_files() -> Result<Vec<PathBuf>, IntellicompError> {
+    let search_path = SchemaSearchPath::load()?;
+    let trust = TrustStore::load()?;
+    let sources = SourceList::load()?;
+    Ok(SchemaFilter::new(&search_path, &trust, &sources).schema_files())
+}
+
+/// Get the schema directories ready for the hook without touching the network. The embedded
+/// schemas are written out, and the schemas from each source are approved the first time
+/// they're used.
+pub fn prepare_schema_directories() -> Result<(), IntellicompError> {
+    extract_embedded_schemas()?;
+    migrate_community_clone()?;
+
+    for source in SourceList::load()?.open_all() {
+        if source.is_fetched() {
+            approve_source(source.as_ref())?;
+        }
+    }
+    Ok(())
+}
+
+/// Fetch the schemas from a source, approving them the first time so that later changes to them
+/// are held back until they're reviewed.
+pub fn fetch_source(source: &dyn SchemaSource) -> Result<FetchOutcome, IntellicompError> {
+    let outcome = source.fetch()?;
+    approve_source(source)?;
+    Ok(outcome)
+}
 
-        std::fs::create_dir_all(schema_dir)?;
-        RepoBuilder::new().clone(
-            "https://github.com/dob9601/intellicomp-schemas.git",
-            schema_dir,
-        )?;
+/// Approve the schemas from a source unless some of them already have been, e.g. for a clone
+/// made by a version without trust levels.
+fn approve_source(source: &dyn SchemaSource) -> Result<(), IntellicompError> {
+    let schema_directory = source.schema_directory();
+    let mut trust = TrustStore::load()?;
+    if !trust.has_approved_any(&schema_directory) {
+        trust.approve_directory(&schema_directory)?;
+        trust.save()?;
     }
-    Ok(schema_dir.to_path_buf().join("schemas"))
+    Ok(())
 }
@@ -0,0 +1,182 @@
+use std::path::Path;
+
+use intellicomp_core::Completion;
+
+use crate::{get_schema_files, CompletableShell, CompletionRequest, IntellicompError};
+
+pub struct Nu;
+
+impl CompletableShell for Nu {
+    fn name(&self) -> &'static str {
+        "nu"
+    }
+
+    /// Nushell only supports a single external completer, so every schema is registered within
+    /// one closure which dispatches on the name of the command being completed. Commands without
+    /// a schema are passed on to the completer which was configured before, if there was one.
+    fn generate_completion_commands(&self) -> Result<Vec<String>, IntellicompError> {
+        let mut schemas = vec![];
+        for schema_file in get_schema_files()? {
+            schemas.extend(self.generate_completions_from_schema(&schema_file)?);
+        }
+
+        let executable = std::env::current_exe()?;
+        let executable = executable
+            .to_str()
+            .ok_or(IntellicompError::InvalidUnicodeInPath)?;
+
+        Ok(get_completer(schemas, executable))
+    }
+
+    fn generate_completions_from_schema(
+        &self,
+        schema_file: &Path,
+    ) -> Result<Vec<String>, IntellicompError> {
+        let command_name = schema_file
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .trim_end_matches(".yaml");
+
+        Ok(vec![format!(
+            "{}: {}",
+            quote(command_name),
+            quote(&schema_file.to_string_lossy())
+        )])
+    }
+
+    fn parse_request(&self, extra: &[String]) -> Result<CompletionRequest, IntellicompError> {
+        // Nushell passes the spans of the command line up to the cursor, which have already
+        // been split into words.
+        if extra.is_empty() {
+            return Err(IntellicompError::InvalidCompletionRequest(
+                "Nushell passed no spans",
+            ));
+        }
+        Ok(CompletionRequest::Words(
+            extra
+                .iter()
+                .map(|span| unquote_span(span).to_string())
+                .collect(),
+        ))
+    }
+
+    fn render_candidates(&self, candidates: &[Completion]) -> String {
+        serde_json::to_string(candidates).expect("Serializing candidates cannot fail")
+    }
+
+    /// Nushell falls back to completing paths when the external completer returns null.
+    fn render_fallback(&self) -> String {
+        "null".to_string()
+    }
+}
+
+/// The closure which completes the commands with schemas, given the entry of each schema within
+/// a record of command names, and the path to intellicomp.
+fn get_completer(schemas: Vec<String>, executable: &str) -> Vec<String> {
+    let mut completion_commands = vec![
+        "let intellicomp_previous_completer = $env.config.completions?.external?.completer?"
+            .to_string(),
+        "$env.config.completions.external.completer = {|spans|".to_string(),
+    ];
+    completion_commands.push("    let schemas = {".to_string());
+    completion_commands.extend(
+        schemas
+            .into_iter()
+            .map(|schema| format!("        {schema}")),
+    );
+    completion_commands.push("    }".to_string());
+    completion_commands.push("    if ($spans.0 in ($schemas | columns)) {".to_string());
+    completion_commands.push(format!(
+        "        ^{} complete nu ($schemas | get $spans.0) ...$spans | complete | get stdout | from json",
+        quote(executable)
+    ));
+    completion_commands.push("    } else if $intellicomp_previous_completer != null {".to_string());
+    completion_commands.push("        do $intellicomp_previous_completer $spans".to_string());
+    completion_commands.push("    }".to_string());
+    completion_commands.push("}".to_string());
+    completion_commands
+}
+
+/// Strip the quotes surrounding a span, as Nushell passes spans exactly as they were typed.
+fn unquote_span(span: &str) -> &str {
+    for quote in ['"', '\'', '`'] {
+        if let Some(unquoted) = span.strip_prefix(quote) {
+            return unquoted.strip_suffix(quote).unwrap_or(unquoted);
+        }
+    }
+    span
+}
+
+/// Quote a string as a Nushell string literal. JSON string literals are valid in Nushell.
+fn quote(string: &str) -> String {
+    serde_json::to_string(string).expect("Serializing a string cannot fail")
+}
+
+#[cfg(test)]
+mod tests {
+    use intellicomp_core::Completion;
+
+    use super::{get_completer, unquote_span, Nu};
+    use crate::{CompletableShell, CompletionRequest};
+
+    #[test]
+    fn test_spans_are_unquoted() {
+        assert_eq!(unquote_span("plain"), "plain");
+        assert_eq!(unquote_span("\"two words\""), "two words");
+        assert_eq!(unquote_span("'single'"), "single");
+        assert_eq!(unquote_span("`backtick`"), "backtick");
+        // The word being completed may not have been closed yet
+        assert_eq!(unquote_span("\"unclosed"), "unclosed");
+        assert_eq!(unquote_span("it's"), "it's");
+
+        let spans = ["git", "commit", "-m", "\"a message\"", "'"].map(String::from);
+        assert_eq!(
+            Nu.parse_request(&spans).unwrap(),
+            CompletionRequest::Words(
+                ["git", "commit", "-m", "a message", ""]
+                    .map(String::from)
+                    .to_vec()
+            )
+        );
+        assert!(Nu.parse_request(&[]).is_err());
+    }
+
+    #[test]
+    fn test_render_candidates_as_json() {
+        let candidates = [Completion {
+            value: "--verbose".to_string(),
+            description: Some("Print \"more\"".to_string()),
+            no_space: false,
+            is_filename: false,
+        }];
+        assert_eq!(
+            Nu.render_candidates(&candidates),
+            r#"[{"value":"--verbose","description":"Print \"more\""}]"#
+        );
+        assert_eq!(Nu.render_fallback(), "null");
+    }
+
+    #[test]
+    fn test_completer_delegates_to_the_previous_one() {
+        let completer = get_completer(
+            vec![r#""git": "/schemas/git.yaml""#.to_string()],
+            "/bin/intellicomp",
+        );
+        assert_eq!(
+            completer.join("\n"),
+            r#"let intellicomp_previous_completer = $env.config.completions?.external?.completer?
+$env.config.completions.external.completer = {|spans|
+    let schemas = {
+        "git": "/schemas/git.yaml"
+    }
+    if ($spans.0 in ($schemas | columns)) {
+        ^"/bin/intellicomp" complete nu ($schemas | get $spans.0) ...$spans | complete | get stdout | from json
+    } else if $intellicomp_previous_completer != null {
+        do $intellicomp_previous_completer $spans
+    }
+}"#
+        );
+    }
+}
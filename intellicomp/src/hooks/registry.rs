@@ -0,0 +1,104 @@
+use crate::{Bash, CompletableShell, Csh, Elvish, Fish, Nu, Xonsh};
+
+/// The set of shells which intellicomp can generate hooks and completions for.
+pub struct ShellRegistry {
+    shells: Vec<Box<dyn CompletableShell>>,
+}
+
+impl ShellRegistry {
+    /// Create a registry without any shells in it.
+    pub fn empty() -> Self {
+        Self { shells: vec![] }
+    }
+
+    /// Add a shell to the registry, replacing any existing shell with the same name.
+    pub fn register(&mut self, shell: impl CompletableShell + 'static) -> &mut Self {
+        self.shells
+            .retain(|existing| existing.name() != shell.name());
+        self.shells.push(Box::new(shell));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn CompletableShell> {
+        self.shells
+            .iter()
+            .find(|shell| shell.name() == name)
+            .map(|shell| shell.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn CompletableShell> {
+        self.shells.iter().map(|shell| shell.as_ref())
+    }
+}
+
+impl Default for ShellRegistry {
+    /// Create a registry containing every shell supported by intellicomp itself.
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry
+            .register(Bash)
+            .register(Fish)
+            .register(Csh)
+            .register(Nu)
+            .register(Elvish)
+            .register(Xonsh);
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use intellicomp_core::Completion;
+
+    use crate::{CompletableShell, CompletionRequest, IntellicompError, ShellRegistry};
+
+    struct MockShell;
+
+    impl CompletableShell for MockShell {
+        fn name(&self) -> &'static str {
+            "bash"
+        }
+
+        fn generate_completions_from_schema(
+            &self,
+            _schema_file: &Path,
+        ) -> Result<Vec<String>, IntellicompError> {
+            Ok(vec![])
+        }
+
+        fn parse_request(&self, extra: &[String]) -> Result<CompletionRequest, IntellicompError> {
+            Ok(CompletionRequest::Words(extra.to_vec()))
+        }
+
+        fn render_candidates(&self, _candidates: &[Completion]) -> String {
+            "mock".to_string()
+        }
+    }
+
+    #[test]
+    fn test_default_registry_contains_builtin_shells() {
+        let registry = ShellRegistry::default();
+
+        assert_eq!(
+            registry
+                .iter()
+                .map(|shell| shell.name())
+                .collect::<Vec<_>>(),
+            vec!["bash", "fish", "csh", "nu", "elvish", "xonsh"]
+        )
+    }
+
+    #[test]
+    fn test_register_replaces_shell_with_same_name() {
+        let mut registry = ShellRegistry::default();
+        registry.register(MockShell);
+
+        let shell = registry.get("bash").expect("Shell was not registered");
+
+        assert_eq!(shell.render_candidates(&[]), "mock");
+        assert!(shell.exporter().is_none());
+        assert_eq!(registry.iter().count(), 6)
+    }
+}
@@ -0,0 +1,169 @@
+use std::path::Path;
+
+use intellicomp_core::Completion;
+
+use super::get_byte_offset;
+use crate::{get_schema_files, CompletableShell, CompletionRequest, IntellicompError};
+
+pub struct Xonsh;
+
+impl CompletableShell for Xonsh {
+    fn name(&self) -> &'static str {
+        "xonsh"
+    }
+
+    /// A single completer is registered for every schema, which dispatches on the name of the
+    /// command being completed and otherwise defers to the other completers.
+    fn generate_completion_commands(&self) -> Result<Vec<String>, IntellicompError> {
+        let mut schemas = vec![];
+        for schema_file in get_schema_files()? {
+            schemas.extend(self.generate_completions_from_schema(&schema_file)?);
+        }
+
+        let executable = std::env::current_exe()?;
+        let executable = executable
+            .to_str()
+            .ok_or(IntellicompError::InvalidUnicodeInPath)?;
+
+        let mut completion_commands = vec![
+            "import json as _intellicomp_json".to_string(),
+            "import subprocess as _intellicomp_subprocess".to_string(),
+            "from xonsh.completers.tools import RichCompletion as _IntellicompRichCompletion"
+                .to_string(),
+            "_intellicomp_schemas = {".to_string(),
+        ];
+        completion_commands.extend(schemas.into_iter().map(|schema| format!("    {schema}")));
+        completion_commands.push("}".to_string());
+        completion_commands.push(format!(
+            r#"def _intellicomp_completer(prefix, line, begidx, endidx, ctx):
+    words = line.split()
+    schema = _intellicomp_schemas.get(words[0]) if words else None
+    if schema is None:
+        return None
+    output = _intellicomp_subprocess.run(
+        [{}, "complete", "xonsh", schema, prefix, line, str(begidx)],
+        capture_output=True,
+        text=True,
+    ).stdout
+    if not output:
+        return None
+    return {{
+        _IntellicompRichCompletion(
+            candidate["value"],
+            description=candidate.get("description", ""),
+            append_space=not candidate.get("no_space", False),
+        )
+        for candidate in _intellicomp_json.loads(output)
+    }}, len(prefix)"#,
+            quote(executable)
+        ));
+        completion_commands
+            .push("completer add intellicomp _intellicomp_completer start".to_string());
+
+        Ok(completion_commands)
+    }
+
+    fn generate_completions_from_schema(
+        &self,
+        schema_file: &Path,
+    ) -> Result<Vec<String>, IntellicompError> {
+        let command_name = schema_file
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .trim_end_matches(".yaml");
+
+        Ok(vec![format!(
+            "{}: {},",
+            quote(command_name),
+            quote(&schema_file.to_string_lossy())
+        )])
+    }
+
+    fn parse_request(&self, extra: &[String]) -> Result<CompletionRequest, IntellicompError> {
+        // Xonsh passes the prefix being completed, the full line and the index at which the
+        // prefix starts, from which the cursor position can be derived.
+        let [prefix, command, prefix_start] = extra else {
+            return Err(IntellicompError::InvalidCompletionRequest(
+                "Expected the prefix, command line and prefix start from xonsh",
+            ));
+        };
+        let prefix_start: usize = prefix_start.parse().map_err(|_| {
+            IntellicompError::InvalidCompletionRequest("The prefix start is not a number")
+        })?;
+
+        Ok(CompletionRequest::Line {
+            cursor_position: get_byte_offset(command, prefix_start + prefix.chars().count()),
+            command: command.clone(),
+        })
+    }
+
+    fn render_candidates(&self, candidates: &[Completion]) -> String {
+        serde_json::to_string(candidates).expect("Serializing candidates cannot fail")
+    }
+}
+
+/// Quote a string as a Python string literal. JSON string literals are valid in Python.
+fn quote(string: &str) -> String {
+    serde_json::to_string(string).expect("Serializing a string cannot fail")
+}
+
+#[cfg(test)]
+mod tests {
+    use intellicomp_core::Completion;
+
+    use super::{quote, Xonsh};
+    use crate::{CompletableShell, CompletionRequest};
+
+    fn parse(prefix: &str, line: &str, prefix_start: &str) -> Option<CompletionRequest> {
+        Xonsh
+            .parse_request(&[prefix, line, prefix_start].map(String::from))
+            .ok()
+    }
+
+    #[test]
+    fn test_parse_request_finds_the_cursor() {
+        assert_eq!(
+            parse("--ver", "git --ver", "4"),
+            Some(CompletionRequest::Line {
+                command: "git --ver".to_string(),
+                cursor_position: 9
+            })
+        );
+
+        // The cursor is within the line, and positions are counted in characters
+        assert_eq!(
+            parse("ré", "git ré other", "4"),
+            Some(CompletionRequest::Line {
+                command: "git ré other".to_string(),
+                cursor_position: 7
+            })
+        );
+
+        assert_eq!(parse("", "git ", "four"), None);
+        assert!(Xonsh.parse_request(&["git ".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_render_candidates_as_json() {
+        let candidates = [Completion {
+            value: "src/".to_string(),
+            description: None,
+            no_space: true,
+            is_filename: true,
+        }];
+
+        assert_eq!(
+            Xonsh.render_candidates(&candidates),
+            r#"[{"value":"src/","no_space":true,"is_filename":true}]"#
+        );
+        assert_eq!(Xonsh.render_candidates(&[]), "[]");
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("/usr/bin/intellicomp"), r#""/usr/bin/intellicomp""#);
+        assert_eq!(quote(r#"it's "quoted""#), r#""it's \"quoted\"""#);
+    }
+}
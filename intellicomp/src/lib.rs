@@ -1,10 +1,71 @@
+use std::error::Error;
+
 mod cli;
 pub use cli::Cli;
 
 mod hooks;
 pub use hooks::*;
 
+mod export;
+pub use export::*;
+
 mod error;
 pub use error::IntellicompError;
 
+mod diagnostics;
+
+mod cache;
+pub use cache::{load_compiled_schema, load_schema_file, SchemaSources, SpecCache};
+
+mod config;
+pub use config::Config;
+
+mod embedded;
+pub use embedded::{extract_embedded_schemas, get_embedded_schema_directory};
+
+mod repository;
+pub use repository::{SchemaRepository, UpdateOutcome};
+
+mod source;
+pub use source::{
+    migrate_community_clone, parse_public_key, FetchOutcome, SchemaSource, SourceConfig,
+    SourceList, Transport, COMMUNITY_SOURCE_NAME,
+};
+
+mod manifest;
+pub use manifest::{Manifest, MANIFEST_FILE_NAME, SIGNATURE_FILE_NAME};
+
+mod trust;
+pub use trust::{find_project_directory, hash_content, hash_file, TrustLevel, TrustStore};
+
+mod search_path;
+pub use search_path::{SchemaDirectory, SchemaDirectoryKind, SchemaFilter, SchemaSearchPath};
+
+#[cfg(unix)]
+mod daemon;
+
 pub mod subcommands;
+use subcommands::{
+    run_autogenerate, run_check, run_complete, run_daemon, run_explain, run_export, run_hook,
+    run_source, run_trust, run_untrust, run_update, run_which,
+};
+
+/// Run intellicomp with the given shells available, e.g. to add support for a shell from
+/// another crate.
+pub fn run(registry: &ShellRegistry) -> Result<(), Box<dyn Error>> {
+    match Cli::parse_with_registry(registry) {
+        Cli::Complete(args) => run_complete(args, registry)?,
+        Cli::Hook(args) => run_hook(args, registry)?,
+        Cli::Autogenerate(args) => run_autogenerate(args)?,
+        Cli::Export(args) => run_export(args, registry)?,
+        Cli::Explain(args) => run_explain(args)?,
+        Cli::Check(args) => run_check(args)?,
+        Cli::Daemon => run_daemon()?,
+        Cli::Update => run_update()?,
+        Cli::Which(args) => run_which(args)?,
+        Cli::Trust(args) => run_trust(args)?,
+        Cli::Untrust(args) => run_untrust(args)?,
+        Cli::Source(command) => run_source(command)?,
+    };
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use ed25519_dalek::{Signature, VerifyingKey};
+
+use crate::IntellicompError;
+
+/// The file at the root of a schema repository listing the SHA-256 hash of each file, in the
+/// format written by `sha256sum`.
+pub const MANIFEST_FILE_NAME: &str = "SHA256SUMS";
+
+/// The ed25519 signature of the manifest, as hex.
+pub const SIGNATURE_FILE_NAME: &str = "SHA256SUMS.sig";
+
+/// The hashes of the files in a schema repository, from a manifest whose signature has been
+/// verified.
+#[derive(Debug)]
+pub struct Manifest {
+    root: PathBuf,
+    hashes: BTreeMap<PathBuf, String>,
+}
+
+impl Manifest {
+    /// Verify a manifest against its signature and parse it. The paths listed in it are relative
+    /// to `root`.
+    pub fn verify(
+        root: &Path,
+        manifest: &[u8],
+        signature: &[u8],
+        public_key: &VerifyingKey,
+    ) -> Result<Self, IntellicompError> {
+        let invalid = || IntellicompError::InvalidSignature(root.display().to_string());
+
+        let signature = std::str::from_utf8(signature)
+            .ok()
+            .and_then(|signature| hex::decode(signature.trim()).ok())
+            .and_then(|signature| <[u8; 64]>::try_from(signature).ok())
+            .ok_or_else(invalid)?;
+        public_key
+            .verify_strict(manifest, &Signature::from_bytes(&signature))
+            .map_err(|_| invalid())?;
+
+        // Only parsed once it's known to be signed
+        let hashes = std::str::from_utf8(manifest)
+            .map_err(|_| invalid())?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let (hash, path) = line.split_once(' ').ok_or_else(invalid)?;
+                let path = path.trim_start_matches([' ', '*']);
+                Ok((root.join(path), hash.to_ascii_lowercase()))
+            })
+            .collect::<Result<_, IntellicompError>>()?;
+
+        Ok(Manifest {
+            root: root.to_path_buf(),
+            hashes,
+        })
+    }
+
+    /// Read and verify the manifest of a checked out repository.
+    pub fn load(root: &Path, public_key: &VerifyingKey) -> Result<Self, IntellicompError> {
+        let root = fs::canonicalize(root)?;
+        let read = |file_name| match fs::read(root.join(file_name)) {
+            Ok(contents) => Ok(contents),
+            Err(error) if error.kind() == ErrorKind::NotFound => Err(
+                IntellicompError::UnsignedSchemas(root.display().to_string()),
+            ),
+            Err(error) => Err(error.into()),
+        };
+
+        Manifest::verify(
+            &root,
+            &read(MANIFEST_FILE_NAME)?,
+            &read(SIGNATURE_FILE_NAME)?,
+            public_key,
+        )
+    }
+
+    /// Whether a file is within the repository, and so has to be listed in the manifest.
+    pub fn covers(&self, path: &Path) -> bool {
+        path.starts_with(&self.root)
+    }
+
+    /// Check that a file is listed in the manifest with the given hash.
+    pub fn check(&self, path: &Path, hash: &str) -> Result<(), IntellicompError> {
+        if self.hashes.get(path).map(String::as_str) == Some(hash) {
+            Ok(())
+        } else {
+            Err(IntellicompError::TamperedSchema(path.display().to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use ed25519_dalek::{Signer, SigningKey};
+
+    use super::Manifest;
+    use crate::{hash_content, IntellicompError};
+
+    #[test]
+    fn test_manifest_must_be_signed_by_the_key() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let hash = hash_content(b"description: demo\n");
+        let manifest = format!("{hash}  schemas/demo.yaml\n");
+        let signature = hex::encode(key.sign(manifest.as_bytes()).to_bytes());
+        let root = Path::new("/repo");
+
+        let verified = Manifest::verify(
+            root,
+            manifest.as_bytes(),
+            signature.as_bytes(),
+            &key.verifying_key(),
+        )
+        .unwrap();
+        assert!(verified.covers(Path::new("/repo/schemas/demo.yaml")));
+        verified
+            .check(Path::new("/repo/schemas/demo.yaml"), &hash)
+            .unwrap();
+        assert!(matches!(
+            verified.check(
+                Path::new("/repo/schemas/demo.yaml"),
+                &hash_content(b"tampered")
+            ),
+            Err(IntellicompError::TamperedSchema(_))
+        ));
+        assert!(matches!(
+            verified.check(Path::new("/repo/schemas/unlisted.yaml"), &hash),
+            Err(IntellicompError::TamperedSchema(_))
+        ));
+
+        let tampered = manifest.replace("demo", "other");
+        assert!(matches!(
+            Manifest::verify(
+                root,
+                tampered.as_bytes(),
+                signature.as_bytes(),
+                &key.verifying_key()
+            ),
+            Err(IntellicompError::InvalidSignature(_))
+        ));
+
+        let other_key = SigningKey::from_bytes(&[8; 32]);
+        assert!(matches!(
+            Manifest::verify(
+                root,
+                manifest.as_bytes(),
+                signature.as_bytes(),
+                &other_key.verifying_key()
+            ),
+            Err(IntellicompError::InvalidSignature(_))
+        ));
+    }
+}
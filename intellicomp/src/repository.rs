@@ -0,0 +1,510 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use ed25519_dalek::VerifyingKey;
+use git2::{build::CheckoutBuilder, build::RepoBuilder, Commit, ErrorCode, Oid, Repository};
+
+use crate::{
+    hash_content, source::remove_if_exists, trust::get_yaml_files, IntellicompError, Manifest,
+    MANIFEST_FILE_NAME, SIGNATURE_FILE_NAME,
+};
+
+/// How old a lockfile must be before it's assumed to have been left behind by a process which
+/// died, rather than belonging to an update which is still running.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// A clone of a repository of schemas, such as the community `intellicomp-schemas` repository.
+///
+/// A repository with a public key must ship a manifest of its schemas signed with that key. Its
+/// schemas are verified against the manifest when it's cloned or updated and again when they're
+/// loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaRepository {
+    directory: PathBuf,
+    url: String,
+    public_key: Option<VerifyingKey>,
+}
+
+/// What an update did to the repository.
+#[derive(Debug, PartialEq, Eq)]
+pub enum UpdateOutcome {
+    UpToDate,
+    FastForwarded { from: Oid, to: Oid },
+}
+
+impl SchemaRepository {
+    pub fn new(directory: PathBuf, url: impl Into<String>) -> Self {
+        SchemaRepository {
+            directory,
+            url: url.into(),
+            public_key: None,
+        }
+    }
+
+    /// Require the repository's manifest to be signed with the given key.
+    pub fn with_public_key(mut self, public_key: VerifyingKey) -> Self {
+        self.public_key = Some(public_key);
+        self
+    }
+
+    /// The directory within the repository which holds the schemas.
+    pub fn schema_directory(&self) -> PathBuf {
+        self.directory.join("schemas")
+    }
+
+    /// Whether the repository has been cloned, which a directory left behind by a clone which
+    /// didn't finish doesn't count as.
+    pub fn exists(&self) -> bool {
+        Repository::open(&self.directory).is_ok()
+    }
+
+    /// Whether a file is within the clone.
+    pub fn contains(&self, path: &Path) -> bool {
+        path.starts_with(fs::canonicalize(&self.directory).unwrap_or(self.directory.clone()))
+    }
+
+    /// Clone the repository unless it has already been cloned.
+    ///
+    /// The clone is made alongside and only moved into place once its schemas match its signed
+    /// manifest, so a clone which fails or is interrupted is never mistaken for a finished one.
+    /// Like [`SchemaRepository::update`], this fails with [`IntellicompError::UpdateInProgress`]
+    /// if another process is already cloning the repository.
+    pub fn clone_if_missing(&self) -> Result<(), IntellicompError> {
+        if self.exists() {
+            return Ok(());
+        }
+
+        if let Some(parent) = self.directory.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let _lock = UpdateLock::acquire(self.directory.with_extension("clone.lock"))?;
+        if self.exists() {
+            return Ok(());
+        }
+
+        let temporary_directory = self
+            .directory
+            .with_extension(format!("{}.tmp", std::process::id()));
+        remove_if_exists(&temporary_directory)?;
+        let clone = SchemaRepository {
+            directory: temporary_directory.clone(),
+            ..self.clone()
+        };
+        let result = RepoBuilder::new()
+            .clone(&self.url, &temporary_directory)
+            .map_err(IntellicompError::from)
+            .and_then(|_| clone.verify_checkout());
+        if let Err(error) = result {
+            remove_if_exists(&temporary_directory)?;
+            return Err(error);
+        }
+
+        // Whatever a clone which didn't finish left behind is replaced
+        remove_if_exists(&self.directory)?;
+        fs::rename(&temporary_directory, &self.directory)?;
+        self.record_update()
+    }
+
+    /// The verified manifest of the clone, or none if the repository doesn't need to be signed.
+    pub fn load_manifest(&self) -> Result<Option<Manifest>, IntellicompError> {
+        self.public_key
+            .as_ref()
+            .map(|public_key| Manifest::load(&self.directory, public_key))
+            .transpose()
+    }
+
+    /// Check every schema and overlay which is checked out against the signed manifest.
+    pub fn verify_checkout(&self) -> Result<(), IntellicompError> {
+        let Some(manifest) = self.load_manifest()? else {
+            return Ok(());
+        };
+
+        for schema_file in get_yaml_files(&self.schema_directory())? {
+            manifest.check(&schema_file, &hash_content(&fs::read(&schema_file)?))?;
+        }
+        Ok(())
+    }
+
+    /// Check every schema and overlay in a commit against the manifest signed in that commit,
+    /// before it's checked out.
+    fn verify_commit(
+        &self,
+        repository: &Repository,
+        commit: &Commit,
+    ) -> Result<(), IntellicompError> {
+        let Some(public_key) = &self.public_key else {
+            return Ok(());
+        };
+
+        let tree = commit.tree()?;
+        let read = |path: &Path| -> Result<Vec<u8>, IntellicompError> {
+            match tree.get_path(path) {
+                Ok(entry) => Ok(entry
+                    .to_object(repository)?
+                    .peel_to_blob()?
+                    .content()
+                    .to_vec()),
+                Err(error) if error.code() == ErrorCode::NotFound => {
+                    Err(IntellicompError::UnsignedSchemas(self.url.clone()))
+                }
+                Err(error) => Err(error.into()),
+            }
+        };
+
+        let manifest = Manifest::verify(
+            Path::new(""),
+            &read(Path::new(MANIFEST_FILE_NAME))?,
+            &read(Path::new(SIGNATURE_FILE_NAME))?,
+            public_key,
+        )?;
+
+        let schemas = match tree.get_path(Path::new("schemas")) {
+            Ok(entry) => entry.to_object(repository)?.peel_to_tree()?,
+            Err(error) if error.code() == ErrorCode::NotFound => return Ok(()),
+            Err(error) => return Err(error.into()),
+        };
+        for entry in schemas.iter() {
+            let Some(name) = entry.name().filter(|name| name.ends_with(".yaml")) else {
+                continue;
+            };
+            let path = Path::new("schemas").join(name);
+            manifest.check(&path, &hash_content(&read(&path)?))?;
+        }
+        Ok(())
+    }
+
+    /// Fetch the branch which is checked out and fast-forward to it. The repository is left as
+    /// it was if the fetched schemas don't match their signed manifest.
+    ///
+    /// Only one update runs at a time, so this fails with [`IntellicompError::UpdateInProgress`]
+    /// if another process is already updating the repository.
+    pub fn update(&self) -> Result<UpdateOutcome, IntellicompError> {
+        let repository = Repository::open(&self.directory)?;
+        let _lock = UpdateLock::acquire(repository.path().join("intellicomp-update.lock"))?;
+
+        let head = repository.head()?;
+        let branch = head
+            .shorthand()
+            .ok_or(IntellicompError::InvalidUnicodeInPath)?
+            .to_string();
+        let from = head.peel_to_commit()?.id();
+
+        log::info!(branch = branch.as_str(); "Fetching schemas");
+        repository
+            .find_remote("origin")?
+            .fetch(&[&branch], None, None)?;
+
+        let upstream = repository.find_reference(&format!("refs/remotes/origin/{branch}"))?;
+        let upstream = repository.reference_to_annotated_commit(&upstream)?;
+        let (analysis, _) = repository.merge_analysis(&[&upstream])?;
+
+        let outcome = if analysis.is_up_to_date() {
+            UpdateOutcome::UpToDate
+        } else if analysis.is_fast_forward() {
+            let to = upstream.id();
+            self.verify_commit(&repository, &repository.find_commit(to)?)?;
+            repository
+                .find_reference(&format!("refs/heads/{branch}"))?
+                .set_target(to, "intellicomp: fast-forward")?;
+            repository.checkout_head(Some(CheckoutBuilder::new().force()))?;
+            UpdateOutcome::FastForwarded { from, to }
+        } else {
+            return Err(IntellicompError::CannotFastForward(branch));
+        };
+
+        self.record_update()?;
+        Ok(outcome)
+    }
+
+    /// Whether at least the given number of days have passed since the repository was last
+    /// cloned or updated.
+    pub fn is_update_due(&self, interval_days: u64) -> bool {
+        let last_update = Repository::open(&self.directory)
+            .ok()
+            .and_then(|repository| fs::metadata(self.get_stamp_file(&repository)).ok())
+            .and_then(|metadata| metadata.modified().ok());
+
+        last_update
+            .and_then(|last_update| SystemTime::now().duration_since(last_update).ok())
+            .is_none_or(|elapsed| elapsed >= Duration::from_secs(interval_days * 24 * 60 * 60))
+    }
+
+    fn record_update(&self) -> Result<(), IntellicompError> {
+        let repository = Repository::open(&self.directory)?;
+        fs::write(self.get_stamp_file(&repository), "")?;
+        Ok(())
+    }
+
+    /// The file whose modification time is when the repository was last updated, kept within
+    /// the git directory so that it isn't mistaken for part of the checkout.
+    fn get_stamp_file(&self, repository: &Repository) -> PathBuf {
+        repository.path().join("intellicomp-last-update")
+    }
+}
+
+/// A lockfile which is removed when dropped.
+pub(crate) struct UpdateLock {
+    path: PathBuf,
+}
+
+impl UpdateLock {
+    pub(crate) fn acquire(path: PathBuf) -> Result<Self, IntellicompError> {
+        if is_stale(&path) {
+            log::warn!(lock:% = path.display(); "Removing a stale lockfile");
+            let _ = fs::remove_file(&path);
+        }
+
+        match fs::File::options().write(true).create_new(true).open(&path) {
+            Ok(_) => Ok(UpdateLock { path }),
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+                Err(IntellicompError::UpdateInProgress)
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+impl Drop for UpdateLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn is_stale(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age >= STALE_LOCK_AGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use ed25519_dalek::{Signer, SigningKey};
+    use git2::{Oid, Repository, Signature};
+
+    use super::{SchemaRepository, UpdateLock, UpdateOutcome};
+    use crate::{hash_content, IntellicompError, MANIFEST_FILE_NAME, SIGNATURE_FILE_NAME};
+
+    /// Commit a single file directly to the main branch of a bare repository.
+    fn commit_schema(upstream: &Repository, contents: &str) -> Oid {
+        commit_signed_schema(upstream, contents, None)
+    }
+
+    /// Commit a single file, along with a manifest signed by the given key which lists the file
+    /// with the given content.
+    fn commit_signed_schema(
+        upstream: &Repository,
+        contents: &str,
+        signed: Option<(&SigningKey, &str)>,
+    ) -> Oid {
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let parent = upstream
+            .find_reference("refs/heads/main")
+            .ok()
+            .map(|reference| reference.peel_to_commit().unwrap());
+
+        let blob = upstream.blob(contents.as_bytes()).unwrap();
+        let mut schemas = upstream.treebuilder(None).unwrap();
+        schemas.insert("demo.yaml", blob, 0o100644).unwrap();
+        let schemas = schemas.write().unwrap();
+        let mut root = upstream.treebuilder(None).unwrap();
+        root.insert("schemas", schemas, 0o040000).unwrap();
+        if let Some((key, signed_contents)) = signed {
+            let manifest = format!(
+                "{}  schemas/demo.yaml\n",
+                hash_content(signed_contents.as_bytes())
+            );
+            let manifest_signature = hex::encode(key.sign(manifest.as_bytes()).to_bytes());
+            let manifest = upstream.blob(manifest.as_bytes()).unwrap();
+            let manifest_signature = upstream.blob(manifest_signature.as_bytes()).unwrap();
+            root.insert(MANIFEST_FILE_NAME, manifest, 0o100644).unwrap();
+            root.insert(SIGNATURE_FILE_NAME, manifest_signature, 0o100644)
+                .unwrap();
+        }
+        let tree = upstream.find_tree(root.write().unwrap()).unwrap();
+
+        upstream
+            .commit(
+                Some("refs/heads/main"),
+                &signature,
+                &signature,
+                "Update schemas",
+                &tree,
+                &parent.iter().collect::<Vec<_>>(),
+            )
+            .unwrap()
+    }
+
+    fn read_schema(repository: &SchemaRepository) -> String {
+        std::fs::read_to_string(repository.schema_directory().join("demo.yaml")).unwrap()
+    }
+
+    #[test]
+    fn test_update_fast_forwards_from_bare_repository() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-repo-{}", std::process::id()));
+        let upstream_path = directory.join("upstream.git");
+        let upstream = Repository::init_bare(&upstream_path).unwrap();
+        upstream.set_head("refs/heads/main").unwrap();
+        let first = commit_schema(&upstream, "description: first\n");
+
+        let repository =
+            SchemaRepository::new(directory.join("clone"), upstream_path.to_str().unwrap());
+        repository.clone_if_missing().unwrap();
+        assert_eq!(read_schema(&repository), "description: first\n");
+        assert!(!repository.is_update_due(1));
+        assert_eq!(repository.update().unwrap(), UpdateOutcome::UpToDate);
+
+        let second = commit_schema(&upstream, "description: second\n");
+        assert_eq!(
+            repository.update().unwrap(),
+            UpdateOutcome::FastForwarded {
+                from: first,
+                to: second
+            }
+        );
+        assert_eq!(read_schema(&repository), "description: second\n");
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_clones_never_share_a_directory() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-clone-{}", std::process::id()));
+        let upstream_path = directory.join("upstream.git");
+        let upstream = Repository::init_bare(&upstream_path).unwrap();
+        upstream.set_head("refs/heads/main").unwrap();
+        commit_schema(&upstream, "description: first\n");
+
+        // Left behind by a clone which was interrupted
+        std::fs::create_dir_all(directory.join("clone/schemas")).unwrap();
+
+        let repository =
+            SchemaRepository::new(directory.join("clone"), upstream_path.to_str().unwrap());
+        assert!(!repository.exists());
+
+        let barrier = std::sync::Barrier::new(4);
+        let results = std::thread::scope(|scope| {
+            let handles = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        barrier.wait();
+                        repository.clone_if_missing()
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert!(results.iter().any(|result| result.is_ok()));
+        for result in results {
+            assert!(matches!(
+                result,
+                Ok(()) | Err(IntellicompError::UpdateInProgress)
+            ));
+        }
+
+        assert!(repository.exists());
+        assert_eq!(read_schema(&repository), "description: first\n");
+        let mut entries = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        entries.sort();
+        assert_eq!(entries, vec!["clone", "upstream.git"]);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-lock-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("update.lock");
+
+        let lock = UpdateLock::acquire(path.clone()).unwrap();
+        assert!(matches!(
+            UpdateLock::acquire(path.clone()),
+            Err(IntellicompError::UpdateInProgress)
+        ));
+        drop(lock);
+        assert!(!Path::new(&path).exists());
+        UpdateLock::acquire(path).unwrap();
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_schemas_must_match_signed_manifest() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-signed-{}", std::process::id()));
+        let upstream_path = directory.join("upstream.git");
+        let upstream = Repository::init_bare(&upstream_path).unwrap();
+        upstream.set_head("refs/heads/main").unwrap();
+        let key = SigningKey::from_bytes(&[42; 32]);
+        commit_schema(&upstream, "description: unsigned\n");
+
+        let repository =
+            SchemaRepository::new(directory.join("clone"), upstream_path.to_str().unwrap())
+                .with_public_key(key.verifying_key());
+        assert!(matches!(
+            repository.clone_if_missing(),
+            Err(IntellicompError::UnsignedSchemas(_))
+        ));
+        assert!(!repository.exists());
+
+        commit_signed_schema(
+            &upstream,
+            "description: signed\n",
+            Some((&key, "description: signed\n")),
+        );
+        repository.clone_if_missing().unwrap();
+        assert_eq!(read_schema(&repository), "description: signed\n");
+        repository.verify_checkout().unwrap();
+
+        let other_key = SigningKey::from_bytes(&[43; 32]);
+        commit_signed_schema(
+            &upstream,
+            "description: forged\n",
+            Some((&other_key, "description: forged\n")),
+        );
+        assert!(matches!(
+            repository.update(),
+            Err(IntellicompError::InvalidSignature(_))
+        ));
+
+        commit_signed_schema(
+            &upstream,
+            "description: tampered\n",
+            Some((&key, "description: signed\n")),
+        );
+        assert!(matches!(
+            repository.update(),
+            Err(IntellicompError::TamperedSchema(_))
+        ));
+        assert_eq!(read_schema(&repository), "description: signed\n");
+
+        std::fs::write(
+            repository.schema_directory().join("demo.yaml"),
+            "description: edited\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            repository.verify_checkout(),
+            Err(IntellicompError::TamperedSchema(_))
+        ));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,547 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use directories::ProjectDirs;
+
+use crate::{
+    find_project_directory, get_embedded_schema_directory, hash_file, Config, IntellicompError,
+    Manifest, SchemaSource, SourceList, TrustLevel, TrustStore,
+};
+
+/// The environment variable holding extra schema directories, separated like `PATH`.
+const SCHEMA_PATH_VARIABLE: &str = "INTELLICOMP_SCHEMA_PATH";
+
+/// The suffix of overlay files, which follows the name of the command they apply to.
+const OVERLAY_SUFFIX: &str = ".overlay.yaml";
+
+/// Where a schema directory in the search path came from, in order of precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SchemaDirectoryKind {
+    /// The `.intellicomp` directory of the project containing the working directory.
+    Project,
+
+    /// A directory listed in `INTELLICOMP_SCHEMA_PATH`.
+    Environment,
+
+    /// A directory listed under `schema_directories` in the config file.
+    Configured,
+
+    /// The `schemas` directory within the user's config directory.
+    User,
+
+    /// A schema source, such as the community repository, added with `intellicomp source`.
+    Source,
+
+    /// The snapshot of the community schemas built into intellicomp, if it was built with them.
+    Embedded,
+
+    /// The directory for schemas installed for every user.
+    System,
+}
+
+impl Display for SchemaDirectoryKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SchemaDirectoryKind::Project => "project",
+            SchemaDirectoryKind::Environment => SCHEMA_PATH_VARIABLE,
+            SchemaDirectoryKind::Configured => "config",
+            SchemaDirectoryKind::User => "user",
+            SchemaDirectoryKind::Source => "source",
+            SchemaDirectoryKind::Embedded => "embedded",
+            SchemaDirectoryKind::System => "system",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaDirectory {
+    pub kind: SchemaDirectoryKind,
+    pub path: PathBuf,
+    pub trust_level: TrustLevel,
+
+    /// The name of the schema source the directory belongs to, if it belongs to one.
+    pub source: Option<String>,
+}
+
+impl SchemaDirectory {
+    /// The name the directory is referred to by, which is the name of its source if it has one.
+    pub fn name(&self) -> String {
+        match &self.source {
+            Some(source) => source.clone(),
+            None => self.kind.to_string(),
+        }
+    }
+}
+
+/// The directories schemas are looked up in. Where several directories hold a schema for the
+/// same command, the one from the earliest directory is used and the others are shadowed.
+///
+/// Directories which aren't trusted are kept so that they can be listed, but no schemas are
+/// found in them.
+#[derive(Debug, Default)]
+pub struct SchemaSearchPath {
+    directories: Vec<SchemaDirectory>,
+}
+
+impl SchemaSearchPath {
+    /// A search path of the given directories, the first of which takes precedence.
+    pub fn new(directories: Vec<SchemaDirectory>) -> Self {
+        SchemaSearchPath { directories }
+    }
+
+    /// The search path for this user, which in order of precedence is made up of the schema
+    /// directory of the current project, the directories in `INTELLICOMP_SCHEMA_PATH`, those in
+    /// the config file, the user's own schema directory, the schema sources, the embedded schemas
+    /// and the system schema directory.
+    pub fn from_environment(config: &Config, trust: &TrustStore, sources: &SourceList) -> Self {
+        let working_directory = std::env::current_dir().unwrap_or_default();
+        SchemaSearchPath::for_working_directory(&working_directory, config, trust, sources)
+    }
+
+    /// The search path for this user as seen from the given directory, whose project schema
+    /// directory takes precedence if it has one.
+    pub fn for_working_directory(
+        working_directory: &Path,
+        config: &Config,
+        trust: &TrustStore,
+        sources: &SourceList,
+    ) -> Self {
+        let mut directories = vec![];
+
+        if let Some(project_directory) = find_project_directory(working_directory) {
+            directories.push((SchemaDirectoryKind::Project, project_directory, None));
+        }
+
+        if let Some(paths) = std::env::var_os(SCHEMA_PATH_VARIABLE) {
+            directories.extend(
+                std::env::split_paths(&paths)
+                    .filter(|path| !path.as_os_str().is_empty())
+                    .map(|path| (SchemaDirectoryKind::Environment, path, None)),
+            );
+        }
+
+        directories.extend(
+            config
+                .schema_directories
+                .iter()
+                .map(|path| (SchemaDirectoryKind::Configured, path.clone(), None)),
+        );
+
+        if let Some(dirs) = ProjectDirs::from("com", "dob9601", "intellicomp") {
+            directories.push((
+                SchemaDirectoryKind::User,
+                dirs.config_dir().join("schemas"),
+                None,
+            ));
+        }
+
+        directories.extend(sources.open_all().into_iter().map(|source| {
+            (
+                SchemaDirectoryKind::Source,
+                source.schema_directory(),
+                Some(source.name().to_string()),
+            )
+        }));
+
+        if let Some(embedded_directory) = get_embedded_schema_directory() {
+            directories.push((SchemaDirectoryKind::Embedded, embedded_directory, None));
+        }
+
+        if cfg!(unix) {
+            directories.push((
+                SchemaDirectoryKind::System,
+                PathBuf::from("/etc/intellicomp/schemas"),
+                None,
+            ));
+        }
+
+        SchemaSearchPath::new(
+            directories
+                .into_iter()
+                .map(|(kind, path, source)| {
+                    // Trust is recorded against the canonical path, so that it can't be
+                    // sidestepped with another path to the same directory
+                    let path = fs::canonicalize(&path).unwrap_or(path);
+                    SchemaDirectory {
+                        kind,
+                        trust_level: trust.level(&path, kind),
+                        path,
+                        source,
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// The search path for this user, reading the config file, trust store and source list.
+    pub fn load() -> Result<Self, IntellicompError> {
+        Ok(SchemaSearchPath::from_environment(
+            &Config::load()?,
+            &TrustStore::load()?,
+            &SourceList::load()?,
+        ))
+    }
+
+    /// Every directory in the search path, including those which aren't trusted.
+    pub fn directories(&self) -> &[SchemaDirectory] {
+        &self.directories
+    }
+
+    /// The directories which schemas may be loaded from.
+    pub fn loaded_directories(&self) -> impl Iterator<Item = &SchemaDirectory> {
+        self.directories
+            .iter()
+            .filter(|directory| directory.trust_level != TrustLevel::Untrusted)
+    }
+
+    /// The directory in the search path which a file is directly within.
+    pub fn get_directory_of(&self, path: &Path) -> Option<&SchemaDirectory> {
+        let parent = path.parent()?;
+        self.directories
+            .iter()
+            .find(|directory| directory.path == parent)
+    }
+
+    /// The schema used for a command, if any directory has one.
+    pub fn find(&self, command_name: &str) -> Option<(&SchemaDirectory, PathBuf)> {
+        self.find_all(command_name).into_iter().next()
+    }
+
+    /// Every schema for a command, starting with the one which is used.
+    pub fn find_all(&self, command_name: &str) -> Vec<(&SchemaDirectory, PathBuf)> {
+        self.loaded_directories()
+            .map(|directory| {
+                (
+                    directory,
+                    directory.path.join(format!("{command_name}.yaml")),
+                )
+            })
+            .filter(|(_, path)| path.is_file())
+            .collect()
+    }
+
+    /// The overlays for a command, in the order they're applied. Overlays from every directory
+    /// are applied, ending with the one which takes precedence.
+    pub fn find_overlays(&self, command_name: &str) -> Vec<(&SchemaDirectory, PathBuf)> {
+        let mut overlays = self
+            .loaded_directories()
+            .map(|directory| {
+                (
+                    directory,
+                    directory
+                        .path
+                        .join(format!("{command_name}{OVERLAY_SUFFIX}")),
+                )
+            })
+            .filter(|(_, path)| path.is_file())
+            .collect::<Vec<_>>();
+        overlays.reverse();
+        overlays
+    }
+
+    /// The schema used for each command which has one, ordered by the name of the command.
+    pub fn schema_files(&self) -> Vec<PathBuf> {
+        self.schema_files_where(|_| true)
+    }
+
+    /// The first schema for each command which satisfies a predicate, ordered by the name of the
+    /// command.
+    fn schema_files_where(&self, predicate: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+        let mut command_names = HashSet::new();
+        let mut schema_files = vec![];
+
+        for directory in self.loaded_directories() {
+            // Directories which don't exist are simply skipped, since most won't
+            let Ok(entries) = fs::read_dir(&directory.path) else {
+                continue;
+            };
+
+            let mut paths = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| is_schema_file(path))
+                .collect::<Vec<_>>();
+            paths.sort();
+
+            for path in paths.into_iter().filter(|path| predicate(path)) {
+                if command_names.insert(get_command_name(&path).to_string()) {
+                    schema_files.push(path);
+                }
+            }
+        }
+
+        schema_files.sort_by(|a, b| get_command_name(a).cmp(get_command_name(b)));
+        schema_files
+    }
+}
+
+/// The signed manifest of a source, if it has one, or `Err` if it couldn't be loaded.
+type SourceManifest = Result<Option<Manifest>, ()>;
+
+/// Decides which schemas in a search path are used. Schemas which are held back by their
+/// source's trust level or don't match their source's signed manifest are passed over, so the
+/// next schema for the command in order of precedence is used instead.
+pub struct SchemaFilter<'a> {
+    search_path: &'a SchemaSearchPath,
+    trust: &'a TrustStore,
+    manifests: Vec<(Box<dyn SchemaSource>, SourceManifest)>,
+}
+
+impl<'a> SchemaFilter<'a> {
+    pub fn new(
+        search_path: &'a SchemaSearchPath,
+        trust: &'a TrustStore,
+        sources: &SourceList,
+    ) -> Self {
+        // A broken manifest shouldn't break the shell, so only the schemas from its source are
+        // passed over
+        let manifests = sources
+            .open_all()
+            .into_iter()
+            .map(|source| {
+                let manifest = source.load_manifest().map_err(|error| {
+                    log::warn!(source = source.name(); "Not using the schema source: {error}");
+                });
+                (source, manifest)
+            })
+            .collect();
+
+        SchemaFilter {
+            search_path,
+            trust,
+            manifests,
+        }
+    }
+
+    /// Whether a schema from the search path may be used.
+    pub fn is_usable(&self, schema_file: &Path) -> bool {
+        let held_back = self
+            .search_path
+            .get_directory_of(schema_file)
+            .is_some_and(|directory| self.trust.is_held_back(schema_file, directory.trust_level));
+        if held_back {
+            log::warn!(spec:% = schema_file.display(); "Schema held back since it changed");
+            return false;
+        }
+
+        let manifest = self
+            .manifests
+            .iter()
+            .find(|(source, _)| source.contains(schema_file))
+            .map(|(_, manifest)| manifest);
+        match manifest {
+            Some(Ok(Some(manifest))) => hash_file(schema_file)
+                .and_then(|hash| manifest.check(schema_file, &hash))
+                .inspect_err(|error| log::warn!("{error}"))
+                .is_ok(),
+            Some(Ok(None)) | None => true,
+            Some(Err(())) => false,
+        }
+    }
+
+    /// The schema used for a command, if any directory has one which may be used.
+    pub fn find(&self, command_name: &str) -> Option<(&'a SchemaDirectory, PathBuf)> {
+        self.search_path
+            .find_all(command_name)
+            .into_iter()
+            .find(|(_, path)| self.is_usable(path))
+    }
+
+    /// The schema used for each command which has one that may be used, ordered by the name of
+    /// the command.
+    pub fn schema_files(&self) -> Vec<PathBuf> {
+        self.search_path
+            .schema_files_where(|schema_file| self.is_usable(schema_file))
+    }
+}
+
+fn is_schema_file(path: &Path) -> bool {
+    let is_overlay = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(OVERLAY_SUFFIX));
+
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|extension| extension == "yaml")
+        && !is_overlay
+}
+
+/// The name of the command a schema file is for, i.e. its name without the extension.
+fn get_command_name(path: &Path) -> &str {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SchemaDirectory, SchemaDirectoryKind, SchemaFilter, SchemaSearchPath};
+    use crate::{Config, SourceList, TrustLevel, TrustStore};
+
+    #[test]
+    fn test_untrusted_project_is_excluded() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-project-{}", std::process::id()));
+        let schema_directory = directory.join(".intellicomp");
+        let working_directory = directory.join("src");
+        std::fs::create_dir_all(&schema_directory).unwrap();
+        std::fs::create_dir_all(&working_directory).unwrap();
+        std::fs::write(schema_directory.join("intellicomp-test-tool.yaml"), "").unwrap();
+
+        let mut trust = TrustStore::default();
+        let search_path = SchemaSearchPath::for_working_directory(
+            &working_directory,
+            &Config::default(),
+            &trust,
+            &SourceList::default(),
+        );
+        let project = &search_path.directories()[0];
+        assert_eq!(project.kind, SchemaDirectoryKind::Project);
+        assert_eq!(project.trust_level, TrustLevel::Untrusted);
+        assert!(search_path.find("intellicomp-test-tool").is_none());
+
+        trust.set_level(project.path.clone(), TrustLevel::Trusted);
+        let search_path = SchemaSearchPath::for_working_directory(
+            &working_directory,
+            &Config::default(),
+            &trust,
+            &SourceList::default(),
+        );
+        let (found, _) = search_path.find("intellicomp-test-tool").unwrap();
+        assert_eq!(found.kind, SchemaDirectoryKind::Project);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_earlier_directories_shadow_later_ones() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-search-{}", std::process::id()));
+        let user = directory.join("user");
+        let community = directory.join("community");
+        let project = directory.join("project");
+        for (path, contents) in [
+            (user.join("git.yaml"), "user"),
+            (community.join("git.yaml"), "community"),
+            (community.join("ls.yaml"), "community"),
+            (community.join("README.md"), "not a schema"),
+            (user.join("ls.overlay.yaml"), "user overlay"),
+            (community.join("ls.overlay.yaml"), "community overlay"),
+            (project.join("git.yaml"), "untrusted"),
+            (project.join("ls.overlay.yaml"), "untrusted overlay"),
+        ] {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+
+        let search_path = SchemaSearchPath::new(vec![
+            SchemaDirectory {
+                kind: SchemaDirectoryKind::Environment,
+                path: directory.join("missing"),
+                trust_level: TrustLevel::Trusted,
+                source: None,
+            },
+            SchemaDirectory {
+                kind: SchemaDirectoryKind::User,
+                path: user.clone(),
+                trust_level: TrustLevel::Trusted,
+                source: None,
+            },
+            SchemaDirectory {
+                kind: SchemaDirectoryKind::Project,
+                path: project.clone(),
+                trust_level: TrustLevel::Untrusted,
+                source: None,
+            },
+            SchemaDirectory {
+                kind: SchemaDirectoryKind::Source,
+                path: community.clone(),
+                trust_level: TrustLevel::Pinned,
+                source: Some("community".to_string()),
+            },
+        ]);
+
+        let (winner, path) = search_path.find("git").unwrap();
+        assert_eq!(winner.kind, SchemaDirectoryKind::User);
+        assert_eq!(path, user.join("git.yaml"));
+        assert_eq!(search_path.find_all("git").len(), 2);
+        assert!(search_path.find("cargo").is_none());
+        assert_eq!(
+            search_path
+                .get_directory_of(&project.join("git.yaml"))
+                .map(|directory| directory.trust_level),
+            Some(TrustLevel::Untrusted)
+        );
+        assert_eq!(
+            search_path
+                .find_overlays("ls")
+                .into_iter()
+                .map(|(_, path)| path)
+                .collect::<Vec<_>>(),
+            vec![
+                community.join("ls.overlay.yaml"),
+                user.join("ls.overlay.yaml")
+            ]
+        );
+
+        assert_eq!(
+            search_path.schema_files(),
+            vec![user.join("git.yaml"), community.join("ls.yaml")]
+        );
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_held_back_schemas_are_passed_over() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-filter-{}", std::process::id()));
+        let pinned = directory.join("pinned");
+        let trusted = directory.join("trusted");
+        for path in [
+            pinned.join("git.yaml"),
+            pinned.join("ls.yaml"),
+            trusted.join("git.yaml"),
+        ] {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let search_path = SchemaSearchPath::new(vec![
+            SchemaDirectory {
+                kind: SchemaDirectoryKind::Source,
+                path: pinned.clone(),
+                trust_level: TrustLevel::Pinned,
+                source: Some("community".to_string()),
+            },
+            SchemaDirectory {
+                kind: SchemaDirectoryKind::System,
+                path: trusted.clone(),
+                trust_level: TrustLevel::Trusted,
+                source: None,
+            },
+        ]);
+        let mut trust = TrustStore::default();
+        trust.approve_directory(&pinned).unwrap();
+        std::fs::write(pinned.join("git.yaml"), "changed").unwrap();
+
+        let filter = SchemaFilter::new(&search_path, &trust, &SourceList::default());
+        let (used, path) = filter.find("git").unwrap();
+        assert_eq!(used.kind, SchemaDirectoryKind::System);
+        assert_eq!(path, trusted.join("git.yaml"));
+        assert_eq!(filter.find("ls").unwrap().1, pinned.join("ls.yaml"));
+        assert_eq!(
+            filter.schema_files(),
+            vec![trusted.join("git.yaml"), pinned.join("ls.yaml")]
+        );
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,810 @@
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use directories::ProjectDirs;
+use ed25519_dalek::VerifyingKey;
+use flate2::read::GzDecoder;
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hash_content, hash_file, repository::UpdateLock, trust::get_yaml_files, IntellicompError,
+    Manifest, SchemaRepository, TrustStore, UpdateOutcome,
+};
+
+/// The name of the source for the community `intellicomp-schemas` repository.
+pub const COMMUNITY_SOURCE_NAME: &str = "community";
+
+const COMMUNITY_REPOSITORY_URL: &str = "https://github.com/dob9601/intellicomp-schemas.git";
+
+/// The file within an extracted archive recording the hash of the archive it came from.
+const ARCHIVE_STAMP_FILE_NAME: &str = ".archive";
+
+/// What fetching a source did.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FetchOutcome {
+    /// The schemas were fetched for the first time.
+    Fetched,
+    UpToDate,
+
+    /// The schemas changed between the given versions, e.g. commits.
+    Updated {
+        from: String,
+        to: String,
+    },
+}
+
+/// Somewhere schemas are fetched from, such as the community repository or an internal mirror
+/// of it.
+pub trait SchemaSource {
+    fn name(&self) -> &str;
+
+    /// The directory holding the source's schemas once they've been fetched.
+    fn schema_directory(&self) -> PathBuf;
+
+    fn is_fetched(&self) -> bool;
+
+    /// Whether fetching the source needs the network, and so isn't done when offline.
+    fn uses_network(&self) -> bool {
+        false
+    }
+
+    /// Fetch the schemas, or bring them up to date if they've been fetched before.
+    fn fetch(&self) -> Result<FetchOutcome, IntellicompError>;
+
+    /// Whether at least the given number of days have passed since the source was last fetched.
+    fn is_update_due(&self, _interval_days: u64) -> bool {
+        false
+    }
+
+    /// Whether a file is among those fetched for the source.
+    fn contains(&self, path: &Path) -> bool {
+        let directory = self.schema_directory();
+        path.starts_with(fs::canonicalize(&directory).unwrap_or(directory))
+    }
+
+    /// The verified manifest of the source, or none if it doesn't need to be signed.
+    fn load_manifest(&self) -> Result<Option<Manifest>, IntellicompError> {
+        Ok(None)
+    }
+
+    /// Delete whatever was fetched for the source.
+    fn remove(&self) -> Result<(), IntellicompError> {
+        Ok(())
+    }
+}
+
+/// A git repository, whose schemas are in its `schemas` directory.
+pub struct GitSource {
+    name: String,
+    url: String,
+    repository: SchemaRepository,
+}
+
+impl SchemaSource for GitSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn schema_directory(&self) -> PathBuf {
+        self.repository.schema_directory()
+    }
+
+    fn is_fetched(&self) -> bool {
+        self.repository.exists()
+    }
+
+    fn uses_network(&self) -> bool {
+        !self.url.starts_with("file://") && !Path::new(&self.url).exists()
+    }
+
+    fn fetch(&self) -> Result<FetchOutcome, IntellicompError> {
+        if !self.repository.exists() {
+            self.repository.clone_if_missing()?;
+            return Ok(FetchOutcome::Fetched);
+        }
+
+        Ok(match self.repository.update()? {
+            UpdateOutcome::UpToDate => FetchOutcome::UpToDate,
+            UpdateOutcome::FastForwarded { from, to } => FetchOutcome::Updated {
+                from: format!("{from:.7}"),
+                to: format!("{to:.7}"),
+            },
+        })
+    }
+
+    fn is_update_due(&self, interval_days: u64) -> bool {
+        self.repository.is_update_due(interval_days)
+    }
+
+    fn contains(&self, path: &Path) -> bool {
+        self.repository.contains(path)
+    }
+
+    fn load_manifest(&self) -> Result<Option<Manifest>, IntellicompError> {
+        self.repository.load_manifest()
+    }
+
+    fn remove(&self) -> Result<(), IntellicompError> {
+        remove_if_exists(&get_fetch_directory(&self.name))
+    }
+}
+
+/// A directory of schemas which is used where it is, e.g. a checkout kept up to date by other
+/// means.
+pub struct DirectorySource {
+    name: String,
+    path: PathBuf,
+    public_key: Option<VerifyingKey>,
+}
+
+impl SchemaSource for DirectorySource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn schema_directory(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn is_fetched(&self) -> bool {
+        self.path.is_dir()
+    }
+
+    fn fetch(&self) -> Result<FetchOutcome, IntellicompError> {
+        if !self.path.is_dir() {
+            return Err(IntellicompError::MissingSchemaDirectory(
+                self.path.display().to_string(),
+            ));
+        }
+        verify_schemas(self.load_manifest()?, &self.path)?;
+        Ok(FetchOutcome::UpToDate)
+    }
+
+    fn load_manifest(&self) -> Result<Option<Manifest>, IntellicompError> {
+        self.public_key
+            .as_ref()
+            .map(|public_key| Manifest::load(&self.path, public_key))
+            .transpose()
+    }
+}
+
+/// A `.tar.gz` bundle of schemas, either at its root or in a `schemas` directory, which is
+/// extracted whenever it changes.
+pub struct ArchiveSource {
+    name: String,
+    archive: PathBuf,
+    directory: PathBuf,
+    public_key: Option<VerifyingKey>,
+}
+
+impl ArchiveSource {
+    fn read_stamp(directory: &Path) -> Option<String> {
+        fs::read_to_string(directory.join(ARCHIVE_STAMP_FILE_NAME)).ok()
+    }
+
+    fn get_schema_directory(directory: &Path) -> PathBuf {
+        let schemas = directory.join("schemas");
+        if schemas.is_dir() {
+            schemas
+        } else {
+            directory.to_path_buf()
+        }
+    }
+}
+
+impl SchemaSource for ArchiveSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn schema_directory(&self) -> PathBuf {
+        ArchiveSource::get_schema_directory(&self.directory)
+    }
+
+    fn is_fetched(&self) -> bool {
+        ArchiveSource::read_stamp(&self.directory).is_some()
+    }
+
+    fn fetch(&self) -> Result<FetchOutcome, IntellicompError> {
+        let hash = hash_file(&self.archive)?;
+        let previous = ArchiveSource::read_stamp(&self.directory);
+        if previous.as_ref() == Some(&hash) {
+            return Ok(FetchOutcome::UpToDate);
+        }
+
+        // Extracted alongside and then swapped in, so that the previous schemas stay in place if
+        // the archive can't be used
+        let temporary_directory = self
+            .directory
+            .with_extension(format!("{}.tmp", std::process::id()));
+        remove_if_exists(&temporary_directory)?;
+        fs::create_dir_all(&temporary_directory)?;
+        let result = tar::Archive::new(GzDecoder::new(fs::File::open(&self.archive)?))
+            .unpack(&temporary_directory)
+            .map_err(IntellicompError::from)
+            .and_then(|_| {
+                let manifest = self
+                    .public_key
+                    .as_ref()
+                    .map(|public_key| Manifest::load(&temporary_directory, public_key))
+                    .transpose()?;
+                verify_schemas(
+                    manifest,
+                    &ArchiveSource::get_schema_directory(&temporary_directory),
+                )
+            });
+        if let Err(error) = result {
+            remove_if_exists(&temporary_directory)?;
+            return Err(error);
+        }
+        fs::write(temporary_directory.join(ARCHIVE_STAMP_FILE_NAME), &hash)?;
+
+        remove_if_exists(&self.directory)?;
+        fs::rename(&temporary_directory, &self.directory)?;
+
+        Ok(match previous {
+            Some(previous) => FetchOutcome::Updated {
+                from: previous.chars().take(7).collect(),
+                to: hash.chars().take(7).collect(),
+            },
+            None => FetchOutcome::Fetched,
+        })
+    }
+
+    fn contains(&self, path: &Path) -> bool {
+        path.starts_with(fs::canonicalize(&self.directory).unwrap_or(self.directory.clone()))
+    }
+
+    fn load_manifest(&self) -> Result<Option<Manifest>, IntellicompError> {
+        self.public_key
+            .as_ref()
+            .map(|public_key| Manifest::load(&self.directory, public_key))
+            .transpose()
+    }
+
+    fn remove(&self) -> Result<(), IntellicompError> {
+        remove_if_exists(&self.directory)
+    }
+}
+
+/// How a source's schemas are fetched.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "transport", rename_all = "snake_case")]
+pub enum Transport {
+    /// A git repository at any URL git understands, including `file://` URLs and paths.
+    Git { url: String },
+
+    /// A local directory of schemas.
+    Directory { path: PathBuf },
+
+    /// A local `.tar.gz` bundle of schemas.
+    Archive { path: PathBuf },
+}
+
+impl Transport {
+    /// The transport for a location, going by its form: archives end in `.tar.gz` or `.tgz`,
+    /// directories which aren't git repositories are used as they are and anything else is
+    /// cloned with git.
+    pub fn from_location(location: &str) -> Self {
+        let path = Path::new(location.strip_prefix("file://").unwrap_or(location));
+        let path = fs::canonicalize(path).unwrap_or(path.to_path_buf());
+
+        if location.ends_with(".tar.gz") || location.ends_with(".tgz") {
+            Transport::Archive { path }
+        } else if path.is_dir() && !path.join(".git").exists() && !location.ends_with(".git") {
+            Transport::Directory { path }
+        } else {
+            Transport::Git {
+                url: location.to_string(),
+            }
+        }
+    }
+}
+
+impl Display for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transport::Git { url } => write!(f, "git {url}"),
+            Transport::Directory { path } => write!(f, "directory {}", path.display()),
+            Transport::Archive { path } => write!(f, "archive {}", path.display()),
+        }
+    }
+}
+
+/// A schema source as it's configured.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceConfig {
+    pub name: String,
+
+    #[serde(flatten)]
+    pub transport: Transport,
+
+    /// The ed25519 key, as hex, which the source's manifest must be signed with. Sources without
+    /// one don't need to be signed.
+    #[serde(default, with = "public_key", skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<VerifyingKey>,
+}
+
+impl SourceConfig {
+    /// The community `intellicomp-schemas` repository. It doesn't publish a signed manifest yet,
+    /// so it has no public key, its schemas are only pinned and fetching it warns that it's
+    /// unsigned.
+    pub fn community() -> Self {
+        SourceConfig {
+            name: COMMUNITY_SOURCE_NAME.to_string(),
+            transport: Transport::Git {
+                url: COMMUNITY_REPOSITORY_URL.to_string(),
+            },
+            public_key: None,
+        }
+    }
+
+    /// Warn that the source's schemas can't be verified when it has no public key. Nothing is
+    /// refused, since the schemas are still pinned, but the user should know before trusting
+    /// them.
+    pub(crate) fn warn_if_unsigned(&self) {
+        if self.public_key.is_some() {
+            return;
+        }
+        log::warn!(source = self.name; "Fetching a schema source which isn't signed");
+        eprintln!(
+            "warning: {} isn't signed, so its schemas can't be verified. Review changed schemas \
+             before approving them with `intellicomp trust --pin {}`.",
+            self.name, self.name
+        );
+    }
+
+    pub fn open(&self) -> Box<dyn SchemaSource> {
+        let name = self.name.clone();
+        let public_key = self.public_key;
+
+        match &self.transport {
+            Transport::Git { url } => {
+                let mut repository = SchemaRepository::new(get_fetch_directory(&name), url);
+                if let Some(public_key) = public_key {
+                    repository = repository.with_public_key(public_key);
+                }
+                Box::new(GitSource {
+                    name,
+                    url: url.clone(),
+                    repository,
+                })
+            }
+            Transport::Directory { path } => Box::new(DirectorySource {
+                name,
+                path: path.clone(),
+                public_key,
+            }),
+            Transport::Archive { path } => Box::new(ArchiveSource {
+                directory: get_fetch_directory(&name),
+                name,
+                archive: path.clone(),
+                public_key,
+            }),
+        }
+    }
+}
+
+/// The schema sources, in order of precedence, kept in `sources.yaml` within the intellicomp
+/// config directory. Only the community repository is used if there isn't one.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SourceList {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+
+    pub sources: Vec<SourceConfig>,
+}
+
+impl Default for SourceList {
+    fn default() -> Self {
+        SourceList {
+            path: None,
+            sources: vec![SourceConfig::community()],
+        }
+    }
+}
+
+impl SourceList {
+    /// Read the source list from the config directory.
+    pub fn load() -> Result<Self, IntellicompError> {
+        match get_sources_path() {
+            Some(path) => SourceList::load_from(path),
+            None => Ok(SourceList::default()),
+        }
+    }
+
+    pub fn load_from(path: PathBuf) -> Result<Self, IntellicompError> {
+        let mut list: SourceList = match fs::read_to_string(&path) {
+            Ok(contents) => serde_yaml::from_str(&contents).map_err(|error| {
+                IntellicompError::InvalidConfig {
+                    path: path.display().to_string(),
+                    error,
+                }
+            })?,
+            Err(error) if error.kind() == ErrorKind::NotFound => SourceList::default(),
+            Err(error) => return Err(error.into()),
+        };
+        list.path = Some(path);
+        Ok(list)
+    }
+
+    /// Write the source list, replacing the previous one in a single step.
+    pub fn save(&self) -> Result<(), IntellicompError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temporary_file = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&temporary_file, serde_yaml::to_string(self)?)?;
+        fs::rename(temporary_file, path)?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SourceConfig> {
+        self.sources.iter().find(|source| source.name == name)
+    }
+
+    /// Add a source after the existing ones, so that it has the lowest precedence.
+    pub fn add(&mut self, source: SourceConfig) -> Result<(), IntellicompError> {
+        let is_valid_name = !source.name.is_empty()
+            && source
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !is_valid_name {
+            return Err(IntellicompError::InvalidSourceName(source.name));
+        }
+        if self.get(&source.name).is_some() {
+            return Err(IntellicompError::SourceExists(source.name));
+        }
+
+        self.sources.push(source);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<SourceConfig, IntellicompError> {
+        let position = self
+            .sources
+            .iter()
+            .position(|source| source.name == name)
+            .ok_or_else(|| IntellicompError::UnknownSource(name.to_string()))?;
+        Ok(self.sources.remove(position))
+    }
+
+    pub fn open_all(&self) -> Vec<Box<dyn SchemaSource>> {
+        self.sources.iter().map(SourceConfig::open).collect()
+    }
+}
+
+pub fn get_sources_path() -> Option<PathBuf> {
+    let dirs = ProjectDirs::from("com", "dob9601", "intellicomp")?;
+    Some(dirs.config_dir().join("sources.yaml"))
+}
+
+/// Where a source's schemas are fetched to, which is a directory of its own so that removing or
+/// refetching one source never touches another.
+fn get_fetch_directory(name: &str) -> PathBuf {
+    let dirs = ProjectDirs::from("com", "dob9601", "intellicomp").unwrap();
+    dirs.data_dir().join("sources").join(name)
+}
+
+/// Move a community clone made before each source had a directory of its own, when it was cloned
+/// into the data directory itself, to where it's fetched to now. The trust levels and approvals
+/// of its schemas move with it, so that they aren't held back.
+pub fn migrate_community_clone() -> Result<(), IntellicompError> {
+    let Some(dirs) = ProjectDirs::from("com", "dob9601", "intellicomp") else {
+        return Ok(());
+    };
+    let data_directory = dirs.data_dir();
+    let directory = get_fetch_directory(COMMUNITY_SOURCE_NAME);
+    if !data_directory.join(".git").is_dir() || directory.exists() {
+        return Ok(());
+    }
+
+    // Shells started together would otherwise split the clone between them
+    let _lock = match UpdateLock::acquire(data_directory.join("intellicomp-migrate.lock")) {
+        Ok(lock) => lock,
+        Err(IntellicompError::UpdateInProgress) => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    if !data_directory.join(".git").is_dir() || directory.exists() {
+        return Ok(());
+    }
+
+    let previous_schema_directory = fs::canonicalize(data_directory.join("schemas")).ok();
+    move_clone(data_directory, &directory)?;
+    log::info!(directory:% = directory.display(); "Moved the community schemas");
+
+    if let Some(previous_schema_directory) = previous_schema_directory {
+        let mut trust = TrustStore::load()?;
+        trust.move_directory(
+            &previous_schema_directory,
+            &fs::canonicalize(directory.join("schemas"))?,
+        );
+        trust.save()?;
+    }
+    Ok(())
+}
+
+/// Check every schema and overlay in a directory against a manifest, if there is one.
+fn verify_schemas(manifest: Option<Manifest>, directory: &Path) -> Result<(), IntellicompError> {
+    let Some(manifest) = manifest else {
+        return Ok(());
+    };
+
+    for schema_file in get_yaml_files(directory)? {
+        manifest.check(&schema_file, &hash_content(&fs::read(&schema_file)?))?;
+    }
+    Ok(())
+}
+
+/// Move a clone to another directory, which may be within it. Only what belongs to the clone is
+/// moved, so any other files alongside it stay where they are.
+fn move_clone(from: &Path, to: &Path) -> Result<(), IntellicompError> {
+    let mut names = Repository::open(from)?
+        .index()?
+        .iter()
+        .filter_map(|entry| {
+            let path = String::from_utf8(entry.path).ok()?;
+            Some(path.split('/').next()?.to_string())
+        })
+        .collect::<BTreeSet<_>>();
+    names.insert(".git".to_string());
+
+    let temporary_directory = to.with_extension(format!("{}.tmp", std::process::id()));
+    remove_if_exists(&temporary_directory)?;
+    fs::create_dir_all(&temporary_directory)?;
+    for name in names {
+        let path = from.join(&name);
+        if path.exists() && !to.starts_with(&path) {
+            fs::rename(path, temporary_directory.join(&name))?;
+        }
+    }
+    fs::rename(&temporary_directory, to)?;
+    Ok(())
+}
+
+pub(crate) fn remove_if_exists(directory: &Path) -> Result<(), IntellicompError> {
+    match fs::remove_dir_all(directory) {
+        Err(error) if error.kind() != ErrorKind::NotFound => Err(error.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Public keys written as hex.
+mod public_key {
+    use ed25519_dalek::VerifyingKey;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        public_key: &Option<VerifyingKey>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match public_key {
+            Some(public_key) => serializer.serialize_str(&hex::encode(public_key.as_bytes())),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<VerifyingKey>, D::Error> {
+        let Some(public_key) = Option::<String>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
+        super::parse_public_key(&public_key)
+            .map(Some)
+            .ok_or_else(|| D::Error::custom(format!("invalid ed25519 public key {public_key}")))
+    }
+}
+
+/// Parse an ed25519 public key written as hex.
+pub fn parse_public_key(public_key: &str) -> Option<VerifyingKey> {
+    let bytes = hex::decode(public_key.trim()).ok()?;
+    VerifyingKey::from_bytes(&bytes.try_into().ok()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use ed25519_dalek::{Signer, SigningKey};
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::{
+        move_clone, ArchiveSource, FetchOutcome, SchemaSource, SourceConfig, SourceList, Transport,
+    };
+    use crate::{hash_content, IntellicompError, MANIFEST_FILE_NAME, SIGNATURE_FILE_NAME};
+
+    /// Write a `.tar.gz` bundle holding a single schema, along with a manifest signed by the given
+    /// key which lists the schema with the given content.
+    fn write_archive(path: &Path, contents: &str, signed: Option<(&SigningKey, &str)>) {
+        let mut files = vec![("schemas/demo.yaml".to_string(), contents.to_string())];
+        if let Some((key, signed_contents)) = signed {
+            let manifest = format!(
+                "{}  schemas/demo.yaml\n",
+                hash_content(signed_contents.as_bytes())
+            );
+            let signature = hex::encode(key.sign(manifest.as_bytes()).to_bytes());
+            files.push((MANIFEST_FILE_NAME.to_string(), manifest));
+            files.push((SIGNATURE_FILE_NAME.to_string(), signature));
+        }
+
+        let mut builder = tar::Builder::new(GzEncoder::new(
+            fs::File::create(path).unwrap(),
+            Compression::default(),
+        ));
+        for (name, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_archive_is_extracted_when_it_changes() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-archive-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let source = ArchiveSource {
+            name: "mirror".to_string(),
+            archive: directory.join("schemas.tar.gz"),
+            directory: directory.join("mirror"),
+            public_key: Some(key.verifying_key()),
+        };
+        let read_schema =
+            || fs::read_to_string(source.schema_directory().join("demo.yaml")).unwrap();
+
+        write_archive(
+            &source.archive,
+            "description: first\n",
+            Some((&key, "description: first\n")),
+        );
+        assert!(!source.is_fetched());
+        assert_eq!(source.fetch().unwrap(), FetchOutcome::Fetched);
+        assert_eq!(read_schema(), "description: first\n");
+        assert_eq!(source.fetch().unwrap(), FetchOutcome::UpToDate);
+
+        // A schema which doesn't match the manifest leaves the previous schemas in place
+        write_archive(
+            &source.archive,
+            "description: tampered\n",
+            Some((&key, "description: first\n")),
+        );
+        assert!(matches!(
+            source.fetch(),
+            Err(IntellicompError::TamperedSchema(_))
+        ));
+        assert_eq!(read_schema(), "description: first\n");
+
+        write_archive(&source.archive, "description: second\n", None);
+        assert!(matches!(
+            source.fetch(),
+            Err(IntellicompError::UnsignedSchemas(_))
+        ));
+
+        write_archive(
+            &source.archive,
+            "description: second\n",
+            Some((&key, "description: second\n")),
+        );
+        assert!(matches!(
+            source.fetch().unwrap(),
+            FetchOutcome::Updated { .. }
+        ));
+        assert_eq!(read_schema(), "description: second\n");
+
+        source.remove().unwrap();
+        assert!(!source.is_fetched());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_clone_is_moved_without_its_neighbours() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-migrate-{}", std::process::id()));
+        let repository = git2::Repository::init(&directory).unwrap();
+        fs::create_dir_all(directory.join("schemas")).unwrap();
+        fs::write(directory.join("schemas/demo.yaml"), "description: demo\n").unwrap();
+        let mut index = repository.index().unwrap();
+        index.add_path(Path::new("schemas/demo.yaml")).unwrap();
+        index.write().unwrap();
+        fs::write(directory.join("intellicomp.log"), "").unwrap();
+        fs::create_dir_all(directory.join("sources/mirror")).unwrap();
+
+        let community = directory.join("sources/community");
+        move_clone(&directory, &community).unwrap();
+
+        assert!(git2::Repository::open(&community).is_ok());
+        assert!(community.join("schemas/demo.yaml").is_file());
+        assert!(!directory.join(".git").exists());
+        assert!(!directory.join("schemas").exists());
+        assert!(directory.join("intellicomp.log").is_file());
+        assert!(directory.join("sources/mirror").is_dir());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_source_list_round_trips() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-sources-{}", std::process::id()));
+        let path = directory.join("sources.yaml");
+        let key = SigningKey::from_bytes(&[7; 32]);
+
+        let mut list = SourceList::load_from(path.clone()).unwrap();
+        assert_eq!(list.sources, vec![SourceConfig::community()]);
+
+        let mirror = SourceConfig {
+            name: "mirror".to_string(),
+            transport: Transport::Git {
+                url: "https://git.example.com/intellicomp-schemas.git".to_string(),
+            },
+            public_key: Some(key.verifying_key()),
+        };
+        list.add(mirror.clone()).unwrap();
+        assert!(matches!(
+            list.add(mirror.clone()),
+            Err(IntellicompError::SourceExists(_))
+        ));
+        assert!(matches!(
+            list.add(SourceConfig {
+                name: "../escape".to_string(),
+                ..mirror.clone()
+            }),
+            Err(IntellicompError::InvalidSourceName(_))
+        ));
+        list.save().unwrap();
+
+        let mut list = SourceList::load_from(path.clone()).unwrap();
+        assert_eq!(list.sources, vec![SourceConfig::community(), mirror]);
+        list.remove("community").unwrap();
+        assert!(matches!(
+            list.remove("community"),
+            Err(IntellicompError::UnknownSource(_))
+        ));
+        list.save().unwrap();
+        assert_eq!(SourceList::load_from(path).unwrap().sources.len(), 1);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_transport_is_inferred_from_location() {
+        let directory = std::env::temp_dir().canonicalize().unwrap();
+
+        assert_eq!(
+            Transport::from_location("https://github.com/dob9601/intellicomp-schemas.git"),
+            Transport::Git {
+                url: "https://github.com/dob9601/intellicomp-schemas.git".to_string()
+            }
+        );
+        assert_eq!(
+            Transport::from_location(directory.to_str().unwrap()),
+            Transport::Directory {
+                path: directory.clone()
+            }
+        );
+        assert_eq!(
+            Transport::from_location("/srv/schemas.tar.gz"),
+            Transport::Archive {
+                path: "/srv/schemas.tar.gz".into()
+            }
+        );
+    }
+}
@@ -1,5 +1,4 @@
 use std::{
-    collections::HashMap,
     ffi::OsStr,
     fs::File,
     io::{BufRead, BufReader},
@@ -63,6 +62,7 @@ pub fn run_autogenerate(args: AutogenerateArgs) -> Result<(), IntellicompError>
                     description: args.description,
                     shorthand: args.short_option,
                     repeatable: false,
+                    required: false,
                     value_type: ValueType::String, // TODO: Can parse this better
                     incompatible_with: vec![],
                 });
@@ -72,6 +72,7 @@ pub fn run_autogenerate(args: AutogenerateArgs) -> Result<(), IntellicompError>
                 description: "".into(),
                 keyword_arguments,
                 positional_arguments: vec![],
+                subcommands: vec![],
             };
             let output_file = File::create(
                 args.output_directory
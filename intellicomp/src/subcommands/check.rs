@@ -0,0 +1,95 @@
+use intellicomp_core::ValidationError;
+
+use crate::cli::CheckArgs;
+use crate::{load_schema_file, IntellicompError};
+
+/// Check that the given arguments are valid for the command described by a schema, printing
+/// each problem found beneath the command line and exiting unsuccessfully if there are any.
+pub fn run_check(args: CheckArgs) -> Result<(), IntellicompError> {
+    let schema = load_schema_file(&args.schema, args.overlays)?;
+
+    let command_name = args
+        .schema
+        .file_stem()
+        .unwrap_or_default()
+        .to_str()
+        .ok_or(IntellicompError::InvalidUnicodeInPath)?;
+
+    let line = get_command_line(command_name, &args.arguments);
+    let errors = schema.command().validate(&line);
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    println!("{line}");
+    for error in &errors {
+        println!("{}", underline(&line, error));
+    }
+    std::process::exit(1);
+}
+
+/// Quote the arguments so that the line splits back into exactly the words given.
+fn get_command_line(command_name: &str, arguments: &[String]) -> String {
+    std::iter::once(command_name)
+        .chain(arguments.iter().map(String::as_str))
+        .map(|word| shlex::quote(word))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Mark the span of an error with carets, followed by the error itself.
+fn underline(line: &str, error: &ValidationError) -> String {
+    let offset = line[..error.span.start].chars().count();
+    let length = line[error.span.clone()].chars().count().max(1);
+
+    format!("{}{} {error}", " ".repeat(offset), "^".repeat(length))
+}
+
+#[cfg(test)]
+mod tests {
+    use intellicomp_core::ValidationErrorKind;
+
+    use super::get_command_line;
+    use crate::export::tests::load_test_schema;
+
+    fn check(arguments: &[&str]) -> Vec<ValidationErrorKind> {
+        let arguments = arguments
+            .iter()
+            .map(|argument| argument.to_string())
+            .collect::<Vec<_>>();
+        load_test_schema()
+            .command()
+            .validate(&get_command_line("vcs", &arguments))
+            .into_iter()
+            .map(|error| error.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_keyword_arguments_with_equals() {
+        assert!(check(&["--color=never", "--message=two words", "apply", "x"]).is_empty());
+        assert_eq!(
+            check(&["--color=sometimes"]),
+            vec![ValidationErrorKind::InvalidEnumMember {
+                argument: "--color".to_string(),
+                value: "sometimes".to_string(),
+                expected: vec![
+                    "always".to_string(),
+                    "auto".to_string(),
+                    "never".to_string()
+                ]
+            }]
+        );
+    }
+
+    #[test]
+    fn test_arguments_after_double_dash_are_positional() {
+        assert!(check(&["apply", "--", "-weird-file"]).is_empty());
+        assert_eq!(
+            check(&["apply", "-weird-file"]),
+            vec![ValidationErrorKind::UnknownOption(
+                "-weird-file".to_string()
+            )]
+        );
+    }
+}
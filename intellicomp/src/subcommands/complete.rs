@@ -1,28 +1,185 @@
 use std::error::Error;
-use std::fs::File;
 use std::io::Write;
+use std::panic::{AssertUnwindSafe, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use intellicomp_core::Command;
+use intellicomp_core::Completion;
 
-use crate::cli::{CompleteArgs, Shell};
+use crate::cli::CompleteArgs;
+use crate::diagnostics::{init_logging, timed};
+use crate::{
+    load_compiled_schema, CompletableShell, IntellicompError, SchemaFilter, SchemaSearchPath,
+    ShellRegistry, SourceList, TrustStore,
+};
 
-pub fn run_complete(args: CompleteArgs) -> Result<(), Box<dyn Error>> {
-    let schema: Command = serde_yaml::from_reader(File::open(args.schema)?)?;
+type PanicHook = dyn Fn(&PanicHookInfo) + Sync + Send + 'static;
 
-    match args.shell {
-        Shell::Bash => {
-            let cursor_position = std::env::var("COMP_POINT")?.parse().unwrap();
+/// Print the candidates for the command line passed by a shell's hook.
+///
+/// Completion must never disrupt the user's terminal, so if the candidates can't be generated
+/// the problem is logged, the shell is told to fall back to its own completion and the process
+/// exits unsuccessfully.
+pub fn run_complete(args: CompleteArgs, registry: &ShellRegistry) -> Result<(), Box<dyn Error>> {
+    init_logging();
 
-            let command: String = std::env::var("COMP_LINE")?;
+    let shell = registry
+        .get(&args.shell)
+        .ok_or(IntellicompError::UnknownShell(args.shell.clone()))?;
 
-            let completions = schema
-                .generate_completions(&command, cursor_position)
-                .unwrap();
+    log::debug!(
+        shell = shell.name(),
+        spec:% = args.schema.display();
+        "Completing"
+    );
 
-            print!("{}", completions.join("\n"));
-            std::io::stdout().flush()?;
+    let result = catch_panics(|| generate_candidates(shell, &args));
+
+    let output = match &result {
+        Ok(candidates) => {
+            log::debug!(count = candidates.len(); "Generated candidates");
+            timed("render", || shell.render_candidates(candidates))
+        }
+        Err(error) => {
+            log::error!(
+                spec:% = args.schema.display();
+                "Failed to complete, falling back to the shell: {error}"
+            );
+            shell.render_fallback()
         }
-        _ => unimplemented!(),
     };
+
+    print!("{output}");
+    std::io::stdout().flush()?;
+    log::logger().flush();
+
+    if result.is_err() {
+        std::process::exit(1);
+    }
     Ok(())
 }
+
+/// Run a function, turning a panic into an error. The panic is only logged, as the default hook
+/// would print it over the user's terminal, and the hook which was set before is put back
+/// afterwards since intellicomp may be running within another program.
+fn catch_panics<T>(
+    function: impl FnOnce() -> Result<T, Box<dyn Error>>,
+) -> Result<T, Box<dyn Error>> {
+    let previous_hook: Arc<PanicHook> = Arc::from(std::panic::take_hook());
+    std::panic::set_hook(Box::new(|info| log::error!("{info}")));
+
+    let result = std::panic::catch_unwind(AssertUnwindSafe(function))
+        .unwrap_or_else(|_| Err("Panicked while generating candidates".into()));
+
+    let _ = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| previous_hook(info)));
+    result
+}
+
+fn generate_candidates(
+    shell: &dyn CompletableShell,
+    args: &CompleteArgs,
+) -> Result<Vec<Completion>, Box<dyn Error>> {
+    let request = timed("parse_request", || shell.parse_request(&args.extra))?;
+    log::debug!(request:? = request; "Received request");
+
+    let schema_file = timed("resolve_spec", || resolve_schema(&args.schema))?;
+
+    #[cfg(unix)]
+    if let Some(socket_path) = crate::daemon::get_socket_path() {
+        let response = timed("daemon", || {
+            crate::daemon::request_candidates(&socket_path, &schema_file, &request)
+        });
+        if let Some(candidates) = response {
+            return Ok(candidates?);
+        }
+    }
+
+    let schema = timed("load_spec", || load_compiled_schema(&schema_file))?;
+    Ok(request.generate_candidates(&schema)?)
+}
+
+/// Find the schema to complete with, which is the one the hook gave unless another takes
+/// precedence from where the command is being completed, such as a schema of the current
+/// project. Schemas which are held back or don't match their source's manifest are passed over,
+/// as they are by the hook.
+fn resolve_schema(schema_file: &Path) -> Result<PathBuf, IntellicompError> {
+    let command_name = schema_file
+        .file_stem()
+        .unwrap_or_default()
+        .to_str()
+        .ok_or(IntellicompError::InvalidUnicodeInPath)?;
+
+    let search_path = SchemaSearchPath::load()?;
+    let trust = TrustStore::load()?;
+    let sources = SourceList::load()?;
+    Ok(SchemaFilter::new(&search_path, &trust, &sources)
+        .find(command_name)
+        .map(|(_, path)| path)
+        .unwrap_or_else(|| schema_file.to_path_buf()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::catch_panics;
+
+    const PANIC_HELPER_VARIABLE: &str = "INTELLICOMP_TEST_PANIC_HELPER";
+
+    #[test]
+    fn test_panics_are_not_passed_on_to_the_previous_hook() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted_calls = Arc::clone(&calls);
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |_| {
+            counted_calls.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        let result: Result<(), _> = catch_panics(|| panic!("Oops"));
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        // The previous hook is back in place
+        let _ = std::panic::catch_unwind(|| panic!("Oops again"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(catch_panics(|| Ok(1)).unwrap(), 1);
+
+        std::panic::set_hook(default_hook);
+    }
+
+    /// Panics within [`catch_panics`] when run by [`test_panics_dont_reach_stderr`], and does
+    /// nothing otherwise.
+    #[test]
+    fn panic_while_generating_candidates() {
+        if std::env::var_os(PANIC_HELPER_VARIABLE).is_none() {
+            return;
+        }
+        let result: Result<(), _> = catch_panics(|| panic!("Oops"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_panics_dont_reach_stderr() {
+        // The test harness captures panic messages, so the panic happens in another process
+        // which is told not to
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "subcommands::complete::tests::panic_while_generating_candidates",
+                "--nocapture",
+                "--test-threads=1",
+            ])
+            .env(PANIC_HELPER_VARIABLE, "1")
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("1 passed"));
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    }
+}
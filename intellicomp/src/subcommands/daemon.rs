@@ -0,0 +1,23 @@
+use crate::diagnostics::init_logging;
+use crate::IntellicompError;
+
+/// Listen for completion requests on the per-user socket until killed.
+#[cfg(unix)]
+pub fn run_daemon() -> Result<(), IntellicompError> {
+    use crate::daemon::{bind, get_socket_path, Daemon};
+
+    init_logging();
+
+    let socket_path = get_socket_path().ok_or(IntellicompError::DaemonNotSupported)?;
+    let listener = bind(&socket_path)?;
+    log::info!(socket:% = socket_path.display(); "Daemon started");
+    println!("Listening on {}", socket_path.display());
+
+    Daemon::default().serve(listener);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn run_daemon() -> Result<(), IntellicompError> {
+    Err(IntellicompError::DaemonNotSupported)
+}
@@ -0,0 +1,98 @@
+use intellicomp_core::Token;
+
+use crate::cli::ExplainArgs;
+use crate::load_schema_file;
+
+/// Print how a command line is parsed against a schema, for finding out why a candidate is or
+/// isn't offered.
+pub fn run_explain(args: ExplainArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let schema = load_schema_file(&args.schema, args.overlays)?;
+
+    let cursor_position = args.cursor.unwrap_or(args.line.len());
+    let state = schema.parse_state(&args.line, cursor_position)?;
+
+    println!("Words:");
+    for (index, word) in state.words().iter().enumerate() {
+        println!("  {index:>3}  {word:?}");
+    }
+
+    println!();
+    println!("Tokens:");
+    let tokens = state
+        .tokens()
+        .iter()
+        .map(|parsed| {
+            (
+                state.words()[parsed.words.clone()]
+                    .iter()
+                    .map(|word| shlex::quote(word))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                describe_token(&parsed.token),
+            )
+        })
+        .collect::<Vec<_>>();
+    print_table(&tokens);
+
+    println!();
+    println!("Excluded arguments:");
+    let exclusions = state
+        .excluded_arguments()
+        .into_iter()
+        .map(|exclusion| (exclusion.argument.to_string(), exclusion.reason.to_string()))
+        .collect::<Vec<_>>();
+    print_table(&exclusions);
+
+    println!();
+    println!("Candidates:");
+    let candidates = state
+        .candidates()?
+        .into_iter()
+        .map(|candidate| (candidate.value, candidate.description.unwrap_or_default()))
+        .collect::<Vec<_>>();
+    print_table(&candidates);
+
+    Ok(())
+}
+
+fn describe_token(token: &Token) -> String {
+    match token {
+        Token::Flag(argument) => format!("flag {argument}"),
+        Token::PopulatedKeywordArgument { argument, value } => {
+            format!("keyword argument {argument} with value {value:?}")
+        }
+        Token::InlineKeywordArgument { argument, value } => {
+            format!("keyword argument {argument} with value {value:?} after an equals sign")
+        }
+        Token::PopulatedPositionalArgument { argument, value } => {
+            format!("positional argument {} with value {value:?}", argument.name)
+        }
+        Token::Subcommand(subcommand) => format!("subcommand {}", subcommand.name),
+        Token::PartialKeywordArgument(partial) => {
+            format!("word being completed as a keyword argument or subcommand, from {partial:?}")
+        }
+        Token::Unknown(word) => format!("unrecognised word {word:?}"),
+    }
+}
+
+/// Print rows of two columns, with the second column aligned.
+fn print_table(rows: &[(String, String)]) {
+    if rows.is_empty() {
+        println!("  (none)");
+        return;
+    }
+
+    let width = rows
+        .iter()
+        .map(|(first, _)| first.chars().count())
+        .max()
+        .unwrap_or_default();
+
+    for (first, second) in rows {
+        if second.is_empty() {
+            println!("  {first}");
+        } else {
+            println!("  {first:width$}   {second}");
+        }
+    }
+}
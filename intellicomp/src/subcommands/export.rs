@@ -0,0 +1,27 @@
+use crate::cli::ExportArgs;
+use crate::{load_schema_file, IntellicompError, ShellRegistry};
+
+pub fn run_export(args: ExportArgs, registry: &ShellRegistry) -> Result<(), IntellicompError> {
+    let shell = registry
+        .get(&args.shell)
+        .ok_or_else(|| IntellicompError::UnknownShell(args.shell.clone()))?;
+    let exporter = shell
+        .exporter()
+        .ok_or(IntellicompError::ExportNotSupported(args.shell))?;
+
+    let schema = load_schema_file(&args.schema, args.overlays)?;
+
+    let command_name = args
+        .schema
+        .file_stem()
+        .unwrap_or_default()
+        .to_str()
+        .ok_or(IntellicompError::InvalidUnicodeInPath)?;
+
+    print!(
+        "{}",
+        exporter.export_completions(command_name, schema.command())
+    );
+
+    Ok(())
+}
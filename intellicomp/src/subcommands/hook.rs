@@ -1,34 +1,138 @@
-use crate::{Bash, CompletableShell, Fish, IntellicompError};
+use std::process::Stdio;
 
-use directories::ProjectDirs;
-use git2::build::RepoBuilder;
+use crate::diagnostics::init_logging;
+use crate::{
+    prepare_schema_directories, Config, IntellicompError, SchemaSource, ShellRegistry, SourceList,
+};
 
 use crate::cli::HookArgs;
 
-pub fn run_hook(args: HookArgs) -> Result<(), IntellicompError> {
-    let dirs = ProjectDirs::from("com", "dob9601", "intellicomp").unwrap();
+/// Print the hook for a shell. The hook never waits on the schema sources: they're fetched and
+/// updated in the background, and are used by shells started once that's finished.
+pub fn run_hook(args: HookArgs, registry: &ShellRegistry) -> Result<(), IntellicompError> {
+    let shell = registry
+        .get(&args.shell)
+        .ok_or(IntellicompError::UnknownShell(args.shell))?;
 
-    let schema_dir = dirs.data_dir();
-    if !schema_dir.exists() {
-        println!("echo 'Pulling schemas from dob9601/intellicomp-schemas'");
+    let config = Config::load()?;
 
-        std::fs::create_dir_all(schema_dir)?;
-        RepoBuilder::new().clone(
-            "https://github.com/dob9601/intellicomp-schemas.git",
-            schema_dir,
-        )?;
+    // A shell should still start if the schemas can't be set up, using those it can find
+    init_logging();
+    if let Err(error) = prepare_schema_directories() {
+        log::error!("Failed to prepare the schema directories: {error}");
+        eprintln!("intellicomp: {error}");
     }
 
-    let completions = match args.shell {
-        crate::cli::Shell::Bash => Bash::generate_completion_commands(),
-        crate::cli::Shell::Fish => Fish::generate_completion_commands(),
-        crate::cli::Shell::Zsh => todo!(),
-        crate::cli::Shell::Csh => todo!(),
-    }?;
+    let completions = shell.generate_completion_commands()?;
 
     println!("{}", completions.join("\n"));
 
+    let sources = SourceList::load()?.open_all();
+    let pending_sources = get_pending_sources(&config, &sources);
+    for source in &pending_sources {
+        if !source.is_fetched() {
+            println!(
+                "echo 'Fetching schemas from {} in the background'",
+                source.name()
+            );
+        }
+    }
+    if !pending_sources.is_empty() {
+        spawn_background_update()?;
+    }
+
     println!("echo 'Intellicomp configured!'");
 
     Ok(())
 }
+
+/// The sources which haven't been fetched yet or are due an update, leaving out those which need
+/// the network when intellicomp is offline.
+fn get_pending_sources<'a>(
+    config: &Config,
+    sources: &'a [Box<dyn SchemaSource>],
+) -> Vec<&'a dyn SchemaSource> {
+    sources
+        .iter()
+        .map(Box::as_ref)
+        .filter(|source| !(config.offline && source.uses_network()))
+        .filter(|source| {
+            !source.is_fetched()
+                || config
+                    .update_interval_days
+                    .is_some_and(|days| source.is_update_due(days))
+        })
+        .collect()
+}
+
+/// Run `update` in a separate process which outlives the hook, so that the shell doesn't wait for
+/// the network. Its output is discarded since the hook's output is evaluated by the shell.
+fn spawn_background_update() -> Result<(), IntellicompError> {
+    std::process::Command::new(std::env::current_exe()?)
+        .arg("update")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::get_pending_sources;
+    use crate::{Config, SourceConfig, Transport};
+
+    #[test]
+    fn test_offline_skips_sources_which_use_the_network() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-hook-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let sources = [
+            SourceConfig {
+                name: format!("intellicomp-test-remote-{}", std::process::id()),
+                transport: Transport::Git {
+                    url: "https://example.com/schemas.git".to_string(),
+                },
+                public_key: None,
+            },
+            SourceConfig {
+                name: "fetched".to_string(),
+                transport: Transport::Directory {
+                    path: directory.clone(),
+                },
+                public_key: None,
+            },
+            SourceConfig {
+                name: "missing".to_string(),
+                transport: Transport::Directory {
+                    path: directory.join("missing"),
+                },
+                public_key: None,
+            },
+        ]
+        .iter()
+        .map(SourceConfig::open)
+        .collect::<Vec<_>>();
+
+        let get_names = |config: &Config| {
+            get_pending_sources(config, &sources)
+                .into_iter()
+                .map(|source| source.name().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            get_names(&Config::default()),
+            vec![sources[0].name(), "missing"]
+        );
+        assert_eq!(
+            get_names(&Config {
+                offline: true,
+                ..Config::default()
+            }),
+            vec!["missing"]
+        );
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
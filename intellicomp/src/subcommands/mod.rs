@@ -6,3 +6,27 @@ pub use hook::run_hook;
 
 mod autogenerate;
 pub use autogenerate::run_autogenerate;
+
+mod export;
+pub use export::run_export;
+
+mod explain;
+pub use explain::run_explain;
+
+mod check;
+pub use check::run_check;
+
+mod daemon;
+pub use daemon::run_daemon;
+
+mod update;
+pub use update::run_update;
+
+mod which;
+pub use which::run_which;
+
+mod trust;
+pub use trust::{run_trust, run_untrust};
+
+mod source;
+pub use source::run_source;
@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use crate::cli::{SourceAddArgs, SourceCommand, SourceRemoveArgs, TransportKind};
+use crate::{
+    fetch_source, parse_public_key, Config, IntellicompError, SourceConfig, SourceList, Transport,
+};
+
+pub fn run_source(command: SourceCommand) -> Result<(), IntellicompError> {
+    match command {
+        SourceCommand::Add(args) => run_source_add(args),
+        SourceCommand::Remove(args) => run_source_remove(args),
+        SourceCommand::List => run_source_list(),
+    }
+}
+
+/// Add a source, which is only kept if its schemas can be fetched unless it can't be fetched
+/// yet because intellicomp is offline.
+fn run_source_add(args: SourceAddArgs) -> Result<(), IntellicompError> {
+    let transport = match args.transport {
+        None => Transport::from_location(&args.location),
+        Some(TransportKind::Git) => Transport::Git { url: args.location },
+        Some(TransportKind::Directory) => Transport::Directory {
+            path: PathBuf::from(args.location),
+        },
+        Some(TransportKind::Archive) => Transport::Archive {
+            path: PathBuf::from(args.location),
+        },
+    };
+    let public_key = args
+        .public_key
+        .map(|public_key| {
+            parse_public_key(&public_key).ok_or(IntellicompError::InvalidPublicKey(public_key))
+        })
+        .transpose()?;
+    let config = SourceConfig {
+        name: args.name,
+        transport,
+        public_key,
+    };
+
+    let mut list = SourceList::load()?;
+    list.add(config.clone())?;
+
+    let source = config.open();
+    if Config::load()?.offline && source.uses_network() {
+        println!(
+            "Added {}, which will be fetched once `offline` is unset",
+            config.name
+        );
+    } else {
+        config.warn_if_unsigned();
+        fetch_source(source.as_ref())?;
+        println!("Added {} and fetched its schemas", config.name);
+    }
+    list.save()
+}
+
+fn run_source_remove(args: SourceRemoveArgs) -> Result<(), IntellicompError> {
+    let mut list = SourceList::load()?;
+    let config = list.remove(&args.name)?;
+    list.save()?;
+
+    config.open().remove()?;
+    println!("Removed {}", config.name);
+    Ok(())
+}
+
+fn run_source_list() -> Result<(), IntellicompError> {
+    for config in SourceList::load()?.sources {
+        let source = config.open();
+        let mut notes = vec![];
+        if config.public_key.is_some() {
+            notes.push("signed");
+        }
+        if !source.is_fetched() {
+            notes.push("not fetched");
+        }
+
+        let notes = match notes.is_empty() {
+            true => String::new(),
+            false => format!(" ({})", notes.join(", ")),
+        };
+        println!("{}: {}{notes}", config.name, config.transport);
+    }
+    Ok(())
+}
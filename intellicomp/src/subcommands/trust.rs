@@ -0,0 +1,79 @@
+use std::{fs, path::PathBuf};
+
+use crate::cli::{TrustArgs, UntrustArgs};
+use crate::{find_project_directory, IntellicompError, SchemaSearchPath, TrustLevel, TrustStore};
+
+pub fn run_trust(args: TrustArgs) -> Result<(), IntellicompError> {
+    let directory = get_source_directory(args.source)?;
+
+    let mut store = TrustStore::load()?;
+    let held_back = store.get_held_back(&directory)?;
+    let level = if args.pin {
+        TrustLevel::Pinned
+    } else {
+        TrustLevel::Trusted
+    };
+    store.set_level(directory.clone(), level);
+    store.approve_directory(&directory)?;
+    store.save()?;
+
+    match level {
+        TrustLevel::Pinned => println!(
+            "Pinned the schemas in {} to their current content",
+            directory.display()
+        ),
+        _ => println!("Trusted the schemas in {}", directory.display()),
+    }
+    if !held_back.is_empty() {
+        println!("Approved {} new or changed schemas", held_back.len());
+    }
+
+    Ok(())
+}
+
+pub fn run_untrust(args: UntrustArgs) -> Result<(), IntellicompError> {
+    let directory = get_source_directory(args.source)?;
+
+    let mut store = TrustStore::load()?;
+    store.set_level(directory.clone(), TrustLevel::Untrusted);
+    store.save()?;
+    println!("Stopped trusting the schemas in {}", directory.display());
+
+    Ok(())
+}
+
+/// The schema directory a source refers to: the directory of that kind in the search path, a
+/// directory in the search path, or the schema directory of the project containing a directory.
+fn get_source_directory(source: Option<String>) -> Result<PathBuf, IntellicompError> {
+    let search_path = SchemaSearchPath::load()?;
+
+    let source = match source {
+        Some(source) => source,
+        None => {
+            let working_directory = std::env::current_dir()?;
+            return find_project_directory(&working_directory).ok_or_else(|| {
+                IntellicompError::ProjectNotFound(working_directory.display().to_string())
+            });
+        }
+    };
+
+    if let Some(directory) = search_path
+        .directories()
+        .iter()
+        .find(|directory| directory.name() == source)
+    {
+        return Ok(directory.path.clone());
+    }
+
+    let path =
+        fs::canonicalize(&source).map_err(|_| IntellicompError::UnknownSource(source.clone()))?;
+    if let Some(directory) = search_path
+        .directories()
+        .iter()
+        .find(|directory| directory.path == path)
+    {
+        return Ok(directory.path.clone());
+    }
+
+    find_project_directory(&path).ok_or(IntellicompError::UnknownSource(source))
+}
@@ -0,0 +1,70 @@
+use std::fs;
+
+use crate::diagnostics::init_logging;
+use crate::{
+    fetch_source, migrate_community_clone, Config, FetchOutcome, IntellicompError,
+    SchemaDirectoryKind, SchemaSource, SourceList, TrustLevel, TrustStore,
+};
+
+/// Fetch the latest schemas from every source. A source which fails doesn't stop the others
+/// from being updated, but its error is returned once they have been.
+pub fn run_update() -> Result<(), IntellicompError> {
+    init_logging();
+
+    let config = Config::load()?;
+    let mut first_error = None;
+    migrate_community_clone()?;
+
+    for source_config in SourceList::load()?.sources {
+        let source = source_config.open();
+        let name = source.name();
+        if config.offline && source.uses_network() {
+            println!("{name}: skipped since `offline` is set");
+            continue;
+        }
+
+        source_config.warn_if_unsigned();
+        match fetch_source(source.as_ref()) {
+            Ok(FetchOutcome::Fetched) => println!("{name}: fetched the schemas"),
+            Ok(FetchOutcome::UpToDate) => println!("{name}: already up to date"),
+            Ok(FetchOutcome::Updated { from, to }) => {
+                println!("{name}: updated from {from} to {to}");
+                report_held_back(source.as_ref())?;
+            }
+            Err(error) => {
+                log::error!(source = name; "Failed to update the schema source: {error}");
+                eprintln!("{name}: {error}");
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+
+    match first_error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Point out the schemas which changed in the update but won't be used until they're approved.
+fn report_held_back(source: &dyn SchemaSource) -> Result<(), IntellicompError> {
+    let schema_directory = fs::canonicalize(source.schema_directory())?;
+    let trust = TrustStore::load()?;
+    if trust.level(&schema_directory, SchemaDirectoryKind::Source) != TrustLevel::Pinned {
+        return Ok(());
+    }
+
+    let held_back = trust.get_held_back(&schema_directory)?;
+    if !held_back.is_empty() {
+        println!(
+            "{} changed schemas are held back until they're reviewed and approved with \
+             `intellicomp trust --pin {}`:",
+            held_back.len(),
+            source.name()
+        );
+        for schema_file in held_back {
+            println!("  {}", schema_file.display());
+        }
+    }
+
+    Ok(())
+}
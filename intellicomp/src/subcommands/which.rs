@@ -0,0 +1,47 @@
+use crate::cli::WhichArgs;
+use crate::{IntellicompError, SchemaFilter, SchemaSearchPath, SourceList, TrustStore};
+
+/// Print the schema used for a command along with the directory it was found in, followed by
+/// the overlays applied to it. Schemas which are held back or don't match their source's
+/// manifest are passed over, as they are when completing.
+pub fn run_which(args: WhichArgs) -> Result<(), IntellicompError> {
+    let search_path = SchemaSearchPath::load()?;
+    let trust = TrustStore::load()?;
+    let sources = SourceList::load()?;
+    let filter = SchemaFilter::new(&search_path, &trust, &sources);
+
+    let Some((directory, path)) = filter.find(&args.command) else {
+        return Err(IntellicompError::SchemaNotFound(args.command));
+    };
+
+    println!(
+        "{} ({}, {})",
+        path.display(),
+        directory.name(),
+        directory.trust_level
+    );
+
+    for (directory, overlay) in search_path.find_overlays(&args.command) {
+        println!("{} (overlay, {})", overlay.display(), directory.trust_level);
+    }
+
+    if args.all {
+        for (other_directory, other_path) in search_path.find_all(&args.command) {
+            if other_path == path {
+                continue;
+            }
+            let note = match filter.is_usable(&other_path) {
+                true => "shadowed",
+                false => "passed over",
+            };
+            println!(
+                "{} ({}, {}, {note})",
+                other_path.display(),
+                other_directory.name(),
+                other_directory.trust_level
+            );
+        }
+    }
+
+    Ok(())
+}
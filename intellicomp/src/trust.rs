@@ -0,0 +1,306 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{IntellicompError, SchemaDirectoryKind};
+
+/// The name of the directory holding a project's own schemas.
+pub const PROJECT_DIRECTORY_NAME: &str = ".intellicomp";
+
+/// How far the schemas from a source are trusted. Schemas feed into the code generated for
+/// shells, so those from sources the user doesn't control shouldn't change without their say.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrustLevel {
+    /// Every schema is loaded, including any changes to them.
+    Trusted,
+
+    /// Schemas are only loaded while their content is what was last approved, so new or changed
+    /// schemas are held back until they're approved again.
+    Pinned,
+
+    /// No schemas are loaded.
+    Untrusted,
+}
+
+impl TrustLevel {
+    /// The trust level of a source which the user hasn't chosen one for. Directories the user or
+    /// an administrator controls and the schemas built into intellicomp are trusted, schema
+    /// sources are pinned to what was first fetched and projects aren't trusted at all.
+    pub fn default_for(kind: SchemaDirectoryKind) -> Self {
+        match kind {
+            SchemaDirectoryKind::Project => TrustLevel::Untrusted,
+            SchemaDirectoryKind::Source => TrustLevel::Pinned,
+            SchemaDirectoryKind::Environment
+            | SchemaDirectoryKind::Configured
+            | SchemaDirectoryKind::User
+            | SchemaDirectoryKind::Embedded
+            | SchemaDirectoryKind::System => TrustLevel::Trusted,
+        }
+    }
+}
+
+impl Display for TrustLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            TrustLevel::Trusted => "trusted",
+            TrustLevel::Pinned => "pinned",
+            TrustLevel::Untrusted => "untrusted",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The trust level chosen for each source and the approved content of each schema, kept in
+/// `trust.yaml` within the intellicomp config directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrustStore {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+
+    /// The trust levels chosen for schema directories, which otherwise have the default for
+    /// their kind.
+    levels: BTreeMap<PathBuf, TrustLevel>,
+
+    /// The SHA-256 hash of each schema or overlay when it was last approved, either with
+    /// `intellicomp trust` or when its source was first fetched. Nothing is recorded when a
+    /// schema is loaded, so only pinned sources are checked against these and changes to
+    /// schemas from trusted sources aren't tracked.
+    hashes: BTreeMap<PathBuf, String>,
+}
+
+impl TrustStore {
+    /// Read the trust store from the config directory, which is empty if it doesn't exist.
+    pub fn load() -> Result<Self, IntellicompError> {
+        match get_trust_store_path() {
+            Some(path) => TrustStore::load_from(path),
+            None => Ok(TrustStore::default()),
+        }
+    }
+
+    pub fn load_from(path: PathBuf) -> Result<Self, IntellicompError> {
+        let mut store: TrustStore = match fs::read_to_string(&path) {
+            Ok(contents) => serde_yaml::from_str(&contents)?,
+            Err(error) if error.kind() == ErrorKind::NotFound => TrustStore::default(),
+            Err(error) => return Err(error.into()),
+        };
+        store.path = Some(path);
+        Ok(store)
+    }
+
+    /// Write the trust store, replacing the previous one in a single step since several
+    /// intellicomp processes may be approving schemas concurrently.
+    pub fn save(&self) -> Result<(), IntellicompError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temporary_file = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&temporary_file, serde_yaml::to_string(self)?)?;
+        fs::rename(temporary_file, path)?;
+        Ok(())
+    }
+
+    /// The trust level of a schema directory.
+    pub fn level(&self, directory: &Path, kind: SchemaDirectoryKind) -> TrustLevel {
+        self.levels
+            .get(directory)
+            .copied()
+            .unwrap_or(TrustLevel::default_for(kind))
+    }
+
+    pub fn set_level(&mut self, directory: PathBuf, level: TrustLevel) {
+        self.levels.insert(directory, level);
+    }
+
+    /// The hash of a schema when it was last approved.
+    pub fn approved_hash(&self, schema_file: &Path) -> Option<&str> {
+        self.hashes.get(schema_file).map(String::as_str)
+    }
+
+    /// Record the current content of a schema as approved, returning whether it changed.
+    pub fn approve(&mut self, schema_file: PathBuf, hash: String) -> bool {
+        self.hashes.insert(schema_file, hash.clone()).as_ref() != Some(&hash)
+    }
+
+    /// Move the trust level of a directory and the approvals of the schemas in it to another
+    /// directory, e.g. once its schemas have been moved there.
+    pub fn move_directory(&mut self, from: &Path, to: &Path) {
+        if let Some(level) = self.levels.remove(from) {
+            self.levels.insert(to.to_path_buf(), level);
+        }
+
+        let moved = self
+            .hashes
+            .keys()
+            .filter(|schema_file| schema_file.parent() == Some(from))
+            .cloned()
+            .collect::<Vec<_>>();
+        for schema_file in moved {
+            let hash = self.hashes.remove(&schema_file).unwrap();
+            self.hashes
+                .insert(to.join(schema_file.file_name().unwrap()), hash);
+        }
+    }
+
+    /// Whether the content of any schema or overlay in a directory has been approved.
+    pub fn has_approved_any(&self, directory: &Path) -> bool {
+        let directory = fs::canonicalize(directory).unwrap_or(directory.to_path_buf());
+        self.hashes
+            .keys()
+            .any(|schema_file| schema_file.parent() == Some(directory.as_path()))
+    }
+
+    /// Approve the current content of every schema and overlay in a directory.
+    pub fn approve_directory(&mut self, directory: &Path) -> Result<(), IntellicompError> {
+        for schema_file in get_yaml_files(directory)? {
+            let hash = hash_file(&schema_file)?;
+            self.approve(schema_file, hash);
+        }
+        Ok(())
+    }
+
+    /// Whether a schema from a source with the given trust level is held back, because the source
+    /// isn't trusted or the schema has changed since it was approved.
+    pub fn is_held_back(&self, schema_file: &Path, level: TrustLevel) -> bool {
+        match level {
+            TrustLevel::Trusted => false,
+            TrustLevel::Pinned => {
+                hash_file(schema_file).ok().as_deref() != self.approved_hash(schema_file)
+            }
+            TrustLevel::Untrusted => true,
+        }
+    }
+
+    /// The schemas and overlays in a directory whose content differs from what was approved,
+    /// and so won't be loaded from a pinned source.
+    pub fn get_held_back(&self, directory: &Path) -> Result<Vec<PathBuf>, IntellicompError> {
+        Ok(get_yaml_files(directory)?
+            .into_iter()
+            .filter(|schema_file| self.is_held_back(schema_file, TrustLevel::Pinned))
+            .collect())
+    }
+}
+
+pub fn get_trust_store_path() -> Option<PathBuf> {
+    let dirs = ProjectDirs::from("com", "dob9601", "intellicomp")?;
+    Some(dirs.config_dir().join("trust.yaml"))
+}
+
+/// The SHA-256 hash of some content, as hex.
+pub fn hash_content(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+pub fn hash_file(path: &Path) -> Result<String, IntellicompError> {
+    Ok(hash_content(&fs::read(path)?))
+}
+
+/// Every YAML file in a directory, sorted, or none if it doesn't exist. Paths are canonical, like
+/// those schemas are loaded from.
+pub(crate) fn get_yaml_files(directory: &Path) -> Result<Vec<PathBuf>, IntellicompError> {
+    let entries = match fs::canonicalize(directory).and_then(fs::read_dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(error.into()),
+    };
+
+    let mut paths = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|e| e == "yaml"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    Ok(paths)
+}
+
+/// The schema directory of the project containing a directory, found by looking for a
+/// `.intellicomp` directory in it and each of its parents.
+pub fn find_project_directory(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|directory| directory.join(PROJECT_DIRECTORY_NAME))
+        .find(|directory| directory.is_dir())
+        .and_then(|directory| fs::canonicalize(directory).ok())
+}
+
+/// Whether a schema file is within a project's schema directory.
+pub fn is_project_schema(schema_file: &Path) -> bool {
+    schema_file
+        .parent()
+        .and_then(|parent| parent.file_name())
+        .is_some_and(|name| name == PROJECT_DIRECTORY_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_project_directory, hash_file, TrustLevel, TrustStore};
+    use crate::SchemaDirectoryKind;
+
+    #[test]
+    fn test_trust_levels_persist() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-trust-{}", std::process::id()));
+        let nested = directory.join("project/scripts/nested");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::create_dir_all(directory.join("project/.intellicomp")).unwrap();
+
+        let project_directory = find_project_directory(&nested).unwrap();
+        assert!(project_directory.ends_with("project/.intellicomp"));
+
+        let store_path = directory.join("trust.yaml");
+        let mut store = TrustStore::load_from(store_path.clone()).unwrap();
+        assert_eq!(
+            store.level(&project_directory, SchemaDirectoryKind::Project),
+            TrustLevel::Untrusted
+        );
+        store.set_level(project_directory.clone(), TrustLevel::Trusted);
+        store.save().unwrap();
+
+        let store = TrustStore::load_from(store_path).unwrap();
+        assert_eq!(
+            store.level(&project_directory, SchemaDirectoryKind::Project),
+            TrustLevel::Trusted
+        );
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_changed_schemas_are_held_back() {
+        let directory =
+            std::env::temp_dir().join(format!("intellicomp-pinned-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let schema = directory.join("git.yaml");
+        std::fs::write(&schema, "description: git\n").unwrap();
+
+        let mut store = TrustStore::default();
+        assert_eq!(
+            store.get_held_back(&directory).unwrap(),
+            vec![schema.clone()]
+        );
+        store.approve_directory(&directory).unwrap();
+        assert!(store.get_held_back(&directory).unwrap().is_empty());
+        assert_eq!(
+            store.approved_hash(&schema),
+            Some(hash_file(&schema).unwrap().as_str())
+        );
+
+        std::fs::write(&schema, "description: compromised\n").unwrap();
+        assert_eq!(store.get_held_back(&directory).unwrap(), vec![schema]);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}